@@ -0,0 +1,148 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+/// How long cached city configs are trusted before they are refreshed. Resy rarely adds
+/// cities so there's no need to check often.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Stored next to the cached configs to know when they need to be refreshed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheMetadata {
+    pub fetched_at: DateTime<Utc>,
+    /// ETag Resy sent with the configs, used to skip downloading them again when they
+    /// haven't changed.
+    pub etag: Option<String>,
+}
+
+impl CacheMetadata {
+    pub fn age(&self) -> chrono::Duration {
+        Utc::now() - self.fetched_at
+    }
+
+    pub fn is_stale(&self, ttl: Duration) -> bool {
+        self.age().to_std().unwrap_or_default() >= ttl
+    }
+}
+
+/// What's currently in the cache.
+#[derive(Debug, Clone)]
+pub struct CacheInfo {
+    pub path: PathBuf,
+    /// Size of the cached configs in bytes.
+    pub size: u64,
+    /// Missing for caches written before metadata was stored, these are always stale.
+    pub metadata: Option<CacheMetadata>,
+}
+
+/// Cache of the restaurant city configs returned by Resy's location config endpoint.
+#[derive(Debug, Clone)]
+pub struct ConfigCache {
+    dir: PathBuf,
+}
+
+impl ConfigCache {
+    pub fn new(dir: impl Into<PathBuf>) -> ConfigCache {
+        ConfigCache { dir: dir.into() }
+    }
+
+    /// The cache in the user's cache directory, shared by every tool.
+    pub fn user_cache() -> Option<ConfigCache> {
+        ProjectDirs::from("xyz", "vec3d", "resy-reserver")
+            .map(|proj_dirs| ConfigCache::new(proj_dirs.cache_dir()))
+    }
+
+    /// The cache used by default for a client talking to `base_url`. Configs from a
+    /// stand-in server shouldn't end up in the cache used for the real API, so only Resy's
+    /// API has one.
+    pub fn for_base_url(base_url: &str) -> Option<ConfigCache> {
+        if base_url == crate::RESY_API_BASE {
+            ConfigCache::user_cache()
+        } else {
+            None
+        }
+    }
+
+    /// Path of the cached configs.
+    pub fn path(&self) -> PathBuf {
+        self.dir.join("restaurants.json")
+    }
+
+    fn metadata_path(&self) -> PathBuf {
+        self.dir.join("restaurants.meta.json")
+    }
+
+    /// Describes the cache, `None` if nothing has been cached yet.
+    pub fn info(&self) -> Result<Option<CacheInfo>> {
+        let path = self.path();
+        let size = match fs::metadata(&path) {
+            Ok(file) => file.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(CacheInfo {
+            path,
+            size,
+            metadata: self.read_metadata(),
+        }))
+    }
+
+    /// Deletes the cached configs, the next load fetches them from Resy.
+    pub fn clear(&self) -> Result<()> {
+        remove_if_exists(&self.path())?;
+        remove_if_exists(&self.metadata_path())
+    }
+
+    /// Reads the cached configs along with their metadata.
+    pub(crate) fn read(&self) -> Result<Option<(String, Option<CacheMetadata>)>> {
+        match fs::read_to_string(self.path()) {
+            Ok(json) => Ok(Some((json, self.read_metadata()))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Metadata that can't be read is treated as missing so the configs get refreshed.
+    fn read_metadata(&self) -> Option<CacheMetadata> {
+        let json = fs::read_to_string(self.metadata_path()).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    pub(crate) fn write(&self, json: &str, etag: Option<String>) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(), json)?;
+        self.write_metadata(&CacheMetadata {
+            fetched_at: Utc::now(),
+            etag,
+        })
+    }
+
+    /// Marks the cached configs as fresh, used when Resy says they haven't changed.
+    pub(crate) fn touch(&self) -> Result<()> {
+        let etag = self.read_metadata().and_then(|m| m.etag);
+        self.write_metadata(&CacheMetadata {
+            fetched_at: Utc::now(),
+            etag,
+        })
+    }
+
+    fn write_metadata(&self, metadata: &CacheMetadata) -> Result<()> {
+        fs::write(self.metadata_path(), serde_json::to_string(metadata)?)?;
+        Ok(())
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    error::{Result, ResyError},
+    matching::{contains_word_prefix, edit_distance, normalize},
+    resy_data::RestaurantCityConfig,
+};
+
+/// Max number of "did you mean" suggestions given when no city matches.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Normalized names a city can be looked up by.
+fn city_keys(city: &RestaurantCityConfig) -> impl Iterator<Item = String> + '_ {
+    [&city.name, &city.url_slug, &city.code]
+        .into_iter()
+        .map(|key| normalize(key))
+        .filter(|key| !key.is_empty())
+}
+
+/// How far the query is from the closest of the city's names.
+fn distance(query: &str, city: &RestaurantCityConfig) -> usize {
+    city_keys(city)
+        .map(|key| edit_distance(query, &key))
+        .min()
+        .unwrap_or(usize::MAX)
+}
+
+/// Whether one of the city's names has a word starting with the query.
+fn contains_query(query: &str, city: &RestaurantCityConfig) -> bool {
+    city_keys(city).any(|key| contains_word_prefix(&key, query))
+}
+
+/// Name shown to the user when listing several cities.
+fn describe(city: &RestaurantCityConfig) -> String {
+    if city.name.is_empty() {
+        return city.url_slug.clone();
+    }
+    format!("{} ({})", city.name, city.url_slug)
+}
+
+/// Looks up a city in the country by its name, url slug or code. Names match regardless
+/// of case, spaces and hyphens. A name that only makes up the start of a word in a city's
+/// name (like "york" or "phila") is accepted as long as it doesn't match several cities.
+/// When nothing matches, the error suggests the cities with the closest names.
+pub fn find_city<'a>(
+    cities: &'a [RestaurantCityConfig],
+    name: &str,
+    country: &str,
+) -> Result<&'a RestaurantCityConfig> {
+    let query = normalize(name);
+    let mut ranked: Vec<(usize, &RestaurantCityConfig)> = cities
+        .iter()
+        .filter(|city| city.country_code.eq_ignore_ascii_case(country))
+        .map(|city| (distance(&query, city), city))
+        .collect();
+    ranked.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.name.cmp(&b.1.name)));
+
+    let exact: Vec<&RestaurantCityConfig> = ranked
+        .iter()
+        .filter(|(distance, _)| *distance == 0)
+        .map(|(_, city)| *city)
+        .collect();
+    let matches = if exact.is_empty() {
+        ranked
+            .iter()
+            .filter(|(_, city)| contains_query(&query, city))
+            .map(|(_, city)| *city)
+            .collect()
+    } else {
+        exact
+    };
+
+    match matches.as_slice() {
+        [city] => Ok(city),
+        [] => {
+            // Anything further off than this is unlikely to be a typo of the name
+            let max_distance = (query.chars().count() / 2).max(2);
+            Err(ResyError::CityNotFound {
+                city: name.to_owned(),
+                suggestions: ranked
+                    .iter()
+                    .filter(|(distance, _)| *distance <= max_distance)
+                    .take(MAX_SUGGESTIONS)
+                    .map(|(_, city)| describe(city))
+                    .collect(),
+            })
+        }
+        _ => Err(ResyError::AmbiguousCity {
+            city: name.to_owned(),
+            candidates: matches.iter().map(|city| describe(city)).collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn city(name: &str, url_slug: &str, code: &str) -> RestaurantCityConfig {
+        RestaurantCityConfig {
+            name: name.to_owned(),
+            code: code.to_owned(),
+            url_slug: url_slug.to_owned(),
+            country_code: "US".to_owned(),
+            latitude: 0.0,
+            longitude: 0.0,
+            time_zone: "EST5EDT".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_find_city() {
+        let cities = vec![
+            city("New York", "new-york-ny", "ny"),
+            city("York", "york-pa", "york"),
+            city("New Haven", "new-haven-ct", "nhv"),
+            city("Boston", "boston-ma", "bos"),
+        ];
+
+        let found = |name| find_city(&cities, name, "us").map(|c| c.url_slug.as_str());
+        assert_eq!(found("new york").unwrap(), "new-york-ny");
+        assert_eq!(found("NEW-YORK-NY").unwrap(), "new-york-ny");
+        assert_eq!(found("york").unwrap(), "york-pa");
+        assert_eq!(found("bos").unwrap(), "boston-ma");
+        assert_eq!(found("bost").unwrap(), "boston-ma");
+        assert!(find_city(&cities, "boston", "GB").is_err());
+
+        match found("new") {
+            Err(ResyError::AmbiguousCity { candidates, .. }) => {
+                assert_eq!(candidates.len(), 2);
+            }
+            other => panic!("expected an ambiguous city, got {:?}", other),
+        }
+        match found("bostn") {
+            Err(ResyError::CityNotFound { suggestions, .. }) => {
+                assert_eq!(suggestions[0], "Boston (boston-ma)");
+            }
+            other => panic!("expected a missing city, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::{header::DATE, Response};
+
+/// HTTP Date headers only go down to the second.
+const DATE_RESOLUTION: TimeDelta = TimeDelta::seconds(1);
+
+/// One response's view of the server's clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    /// Local time the request was sent.
+    pub sent: DateTime<Utc>,
+    /// Local time the response came back.
+    pub received: DateTime<Utc>,
+    /// Server time from the response's Date header, truncated to the second.
+    pub server: DateTime<Utc>,
+}
+
+impl ClockSample {
+    /// Reads the server time from a response, `None` if it has no Date header.
+    pub(crate) fn from_response(
+        response: &Response,
+        sent: DateTime<Utc>,
+        received: DateTime<Utc>,
+    ) -> Option<ClockSample> {
+        let date = response.headers().get(DATE)?.to_str().ok()?;
+        let server = DateTime::parse_from_rfc2822(date).ok()?.to_utc();
+        Some(ClockSample {
+            sent,
+            received,
+            server,
+        })
+    }
+
+    /// Smallest and largest offset the sample allows. The server stamped the response
+    /// somewhere between sending and receiving it, at a time somewhere in the second its
+    /// Date header names.
+    fn bounds(&self) -> (TimeDelta, TimeDelta) {
+        (
+            self.server - self.received,
+            self.server + DATE_RESOLUTION - self.sent,
+        )
+    }
+}
+
+/// How far the server's clock is ahead of the local one. The default trusts the local
+/// clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClockOffset {
+    /// Added to local time to get server time, negative when the local clock is ahead.
+    pub offset: TimeDelta,
+    /// The real offset is within this much of `offset` either way.
+    pub uncertainty: TimeDelta,
+}
+
+impl ClockOffset {
+    /// Narrows the offset down to what every sample agrees on. Samples taken at different
+    /// points within a second see the Date header tick over at different times, so a few
+    /// of them pin the offset down much more precisely than a second. If the samples
+    /// disagree, e.g. because the local clock was adjusted while sampling, the one with the
+    /// quickest round trip is used alone.
+    pub fn estimate(samples: &[ClockSample]) -> Option<ClockOffset> {
+        let lower = samples.iter().map(|s| s.bounds().0).max()?;
+        let upper = samples.iter().map(|s| s.bounds().1).min()?;
+        let (lower, upper) = if lower <= upper {
+            (lower, upper)
+        } else {
+            samples.iter().min_by_key(|s| s.received - s.sent)?.bounds()
+        };
+        Some(ClockOffset {
+            offset: lower + (upper - lower) / 2,
+            uncertainty: (upper - lower) / 2,
+        })
+    }
+
+    /// Current time on the server's clock.
+    pub fn server_now(&self) -> DateTime<Utc> {
+        Utc::now() + self.offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_offset() {
+        let local = DateTime::parse_from_rfc3339("2030-01-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        // Server is 1.3s ahead and takes 20ms to answer
+        let server_ahead = TimeDelta::milliseconds(1300);
+        let sample = |sent_ms: i64| {
+            let sent = local + TimeDelta::milliseconds(sent_ms);
+            let stamped = sent + TimeDelta::milliseconds(10) + server_ahead;
+            ClockSample {
+                sent,
+                received: sent + TimeDelta::milliseconds(20),
+                server: DateTime::from_timestamp(stamped.timestamp(), 0).unwrap(),
+            }
+        };
+
+        let single = ClockOffset::estimate(&[sample(0)]).unwrap();
+        assert!(single.uncertainty > TimeDelta::milliseconds(500));
+
+        let samples: Vec<ClockSample> = (0..5).map(|i| sample(i * 210)).collect();
+        let estimate = ClockOffset::estimate(&samples).unwrap();
+        assert!(estimate.uncertainty <= TimeDelta::milliseconds(150));
+        assert!((estimate.offset - server_ahead).abs() <= estimate.uncertainty);
+
+        // A sample that can't be right leaves only the quickest one to go by
+        let mut inconsistent = samples.clone();
+        inconsistent[2].server += TimeDelta::seconds(5);
+        assert!(ClockOffset::estimate(&inconsistent).is_some());
+        assert!(ClockOffset::estimate(&[]).is_none());
+    }
+}
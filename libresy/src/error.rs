@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    Response, StatusCode,
+};
+use thiserror::Error;
+
+use crate::resy_data::{RestaurantSearchResult, ResyErrorResponse};
+
+/// Errors that can be returned by the [`ResyClient`](crate::ResyClient). Variants are split
+/// up so that callers can decide how to react (retry, re-authenticate, give up, etc.)
+#[derive(Debug, Error)]
+pub enum ResyError {
+    /// The request never got a usable response from Resy (DNS, connection, TLS, timeout, etc.)
+    #[error("network error while talking to Resy: {0}")]
+    Network(#[from] reqwest::Error),
+    /// Resy rejected the api key or auth token. Resy uses both 401 and 419 for this.
+    #[error("Resy rejected the provided credentials (HTTP {status})")]
+    Unauthorized { status: u16 },
+    /// Resy is throttling requests. `retry_after` is populated if Resy told us how long to wait.
+    #[error("rate limited by Resy")]
+    RateLimited { retry_after: Option<Duration> },
+    /// The venue requested does not exist or Resy didn't return any data for it.
+    #[error("venue {0} was not found")]
+    VenueNotFound(String),
+    /// No city in the Resy network matches the name given. `suggestions` holds the cities
+    /// with the closest names.
+    #[error("no city named {city} was found{}", did_you_mean(.suggestions))]
+    CityNotFound {
+        city: String,
+        suggestions: Vec<String>,
+    },
+    /// The name given matches several cities.
+    #[error("{city} matches several cities: {}", .candidates.join(", "))]
+    AmbiguousCity {
+        city: String,
+        candidates: Vec<String>,
+    },
+    /// Several restaurants have exactly the name given, so there's no telling which one was
+    /// meant. `candidates` holds all of them so the caller can pick one.
+    #[error("{name} matches several restaurants: {}", describe_all(.candidates))]
+    AmbiguousRestaurant {
+        name: String,
+        candidates: Vec<RestaurantSearchResult>,
+    },
+    /// Resy sent back data that doesn't match what we expect, most likely because their
+    /// API changed.
+    #[error("unexpected response from Resy: {0}")]
+    Schema(#[from] serde_json::Error),
+    /// A response used to read Resy's clock didn't have a Date header that can be parsed.
+    #[error("Resy didn't send the time in its response")]
+    NoServerDate,
+    /// A slot token isn't in the `rgs://resy/...` format Resy uses.
+    #[error("invalid slot token {token}: {reason}")]
+    InvalidToken { token: String, reason: String },
+    /// The booking could not be made because the slot is no longer available or conflicts
+    /// with an existing reservation.
+    #[error("unable to book reservation: {0}")]
+    BookingConflict(String),
+    /// The account has no card on file, Resy needs one to book most restaurants.
+    #[error("no payment method is on file, add a card to your Resy account")]
+    NoPaymentMethod,
+    /// None of the account's cards match the payment method asked for. `available`
+    /// describes the cards that are on file.
+    #[error("no payment method matches {selector}, cards on file: {}", .available.join(", "))]
+    PaymentMethodNotFound {
+        selector: String,
+        available: Vec<String>,
+    },
+    /// Reading or writing the local restaurant config cache failed.
+    #[error("unable to access the cache: {0}")]
+    Cache(#[from] std::io::Error),
+    /// Resy returned an error status that doesn't fall into one of the more specific
+    /// variants. `code` and `message` come from Resy's error payload when one was sent.
+    #[error("Resy returned HTTP {status}: {message}")]
+    Api {
+        status: u16,
+        code: Option<String>,
+        message: String,
+    },
+    /// The client was configured with values that can't be sent to Resy.
+    #[error("invalid client configuration: {0}")]
+    InvalidConfig(String),
+    /// Credentials couldn't be loaded from profiles.toml or the keyring, or not enough
+    /// of them were provided to create a client.
+    #[error("unable to get credentials: {0}")]
+    Profile(String),
+}
+
+pub type Result<T> = std::result::Result<T, ResyError>;
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        return String::new();
+    }
+    format!(", did you mean {}?", suggestions.join(" or "))
+}
+
+fn describe_all(restaurants: &[RestaurantSearchResult]) -> String {
+    let descriptions: Vec<String> = restaurants
+        .iter()
+        .map(RestaurantSearchResult::describe)
+        .collect();
+    descriptions.join(", ")
+}
+
+impl ResyError {
+    /// Builds the error matching a non-success response from Resy.
+    fn from_response_parts(status: StatusCode, headers: &HeaderMap, body: &str) -> ResyError {
+        match status.as_u16() {
+            401 | 419 => ResyError::Unauthorized {
+                status: status.as_u16(),
+            },
+            429 => ResyError::RateLimited {
+                retry_after: parse_retry_after(headers),
+            },
+            _ => {
+                // Resy usually sends {"status": ..., "message": ..., "code": ...} but
+                // proxies in front of it may send back HTML or nothing at all.
+                let payload: ResyErrorResponse = serde_json::from_str(body).unwrap_or_default();
+                let message = payload
+                    .message
+                    .or_else(|| status.canonical_reason().map(str::to_owned))
+                    .unwrap_or_else(|| "unknown error".to_owned());
+                ResyError::Api {
+                    status: status.as_u16(),
+                    code: payload.code.map(|c| match c {
+                        serde_json::Value::String(s) => s,
+                        other => other.to_string(),
+                    }),
+                    message,
+                }
+            }
+        }
+    }
+
+    /// Converts conflicts returned by the booking endpoint into [`ResyError::BookingConflict`].
+    /// Resy uses 412 when the slot was taken before we could book it and 409 when the user
+    /// already has a conflicting reservation.
+    pub(crate) fn into_booking_error(self) -> ResyError {
+        match self {
+            ResyError::Api {
+                status: 409 | 412,
+                message,
+                ..
+            } => ResyError::BookingConflict(message),
+            other => other,
+        }
+    }
+}
+
+/// Checks the status of a response, turning any non-success status into a [`ResyError`]
+/// built from Resy's error payload.
+pub(crate) async fn check_response(response: Response) -> Result<Response> {
+    let status = response.status();
+    // Only sent for conditional requests, which expect it
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+    let headers = response.headers().clone();
+    let body = response.text().await.unwrap_or_default();
+    Err(ResyError::from_response_parts(status, &headers, &body))
+}
+
+/// Reads the Retry-After header, which can either be a number of seconds or an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_error_from_response() {
+        let headers = HeaderMap::new();
+        let error = ResyError::from_response_parts(
+            StatusCode::PRECONDITION_FAILED,
+            &headers,
+            r#"{"status": 412, "message": "Sorry, that slot is no longer available", "code": 1}"#,
+        );
+        match error {
+            ResyError::Api {
+                status,
+                code,
+                message,
+            } => {
+                assert_eq!(status, 412);
+                assert_eq!(code.as_deref(), Some("1"));
+                assert_eq!(message, "Sorry, that slot is no longer available");
+            }
+            e => panic!("Unexpected error {:?}", e),
+        }
+
+        // Non-JSON bodies should still produce a useful error
+        let error =
+            ResyError::from_response_parts(StatusCode::BAD_GATEWAY, &headers, "<html></html>");
+        assert!(matches!(error, ResyError::Api { status: 502, .. }));
+
+        let error =
+            ResyError::from_response_parts(StatusCode::from_u16(419).unwrap(), &headers, "");
+        assert!(matches!(error, ResyError::Unauthorized { status: 419 }));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        let error = ResyError::from_response_parts(StatusCode::TOO_MANY_REQUESTS, &headers, "");
+        assert!(matches!(
+            error,
+            ResyError::RateLimited {
+                retry_after: Some(d)
+            } if d == Duration::from_secs(3)
+        ));
+    }
+}
@@ -1,45 +1,97 @@
 use std::{
-    collections::HashMap,
-    fs::{self, File},
-    io::BufReader,
+    collections::{HashMap, HashSet},
+    fmt,
+    path::PathBuf,
     str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
 };
 
-use chrono::NaiveDate;
-use directories::ProjectDirs;
+use cache::{ConfigCache, DEFAULT_CACHE_TTL};
+use chrono::{NaiveDate, Utc};
+use clock::{ClockOffset, ClockSample};
+use error::{check_response, Result};
+use futures::{future, stream, Stream, TryStreamExt};
 use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION},
-    Client,
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, ETAG, IF_NONE_MATCH},
+    Client, Request, RequestBuilder, Response, StatusCode,
 };
 use resy_data::{
-    BookToken, GeoFilter, PaymentMethod, ReservationDetails, ReservationDetailsRequest,
-    ReservationSlot, RestaurantCityConfig, RestaurantSearchRequest, RestaurantSearchResult,
-    ResyNotification, ResyNotificationResults,
+    AuthResponse, AvailabilityCalendar, BookResponse, BookingConfirmation, BookingSpecs,
+    DiscoverQuery, DiscoveredVenue, GeoFilter, PaymentMethod, PaymentSelector, RankedRestaurant,
+    ReservationDetails, ReservationDetailsRequest, ReservationSlot, RestaurantCityConfig,
+    RestaurantSearchRequest, RestaurantSearchResult, ResyNotification, ResyNotificationResults,
+    RgsToken, UserAccount, UserReservation, UserReservationsResponse, Venue, VenueAvailability,
 };
+use retry::RetryPolicies;
+use search::{SearchPage, SearchQuery};
+use tokio::task::JoinHandle;
 
+pub mod cache;
+pub mod city;
+pub mod clock;
+pub mod error;
+mod matching;
+pub mod profile;
+pub mod rate_limit;
 pub mod resy_data;
+pub mod retry;
+pub mod search;
+
+pub use error::ResyError;
+pub use profile::{Profile, Profiles};
+pub use rate_limit::{RateLimit, RateLimiter};
+pub use retry::RetryPolicy;
 
 /// Resy apparently checks if the user-agent is a "browser" agent so let's pretend to be Firefox
 static USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:126.0) Gecko/20100101 Firefox/126.0";
-/// Base URL for location based queries
-static RESY_LOCATION_BASE: &str = "https://api.resy.com/3/location";
+/// Default base URL of the Resy API. Can be changed through the builder to point the
+/// client at a local stand-in server.
+pub static RESY_API_BASE: &str = "https://api.resy.com";
 /// URL path to fetch the location config data
-static RESY_CONFIG_URL: &str = "/config";
-/// Base URL for venue search queries
-static RESY_VENUESEARCH_BASE: &str = "https://api.resy.com/3/venuesearch";
-static RESY_VENUESEARCH_SEARCH: &str = "/search";
+static RESY_CONFIG_PATH: &str = "/3/location/config";
+/// URL path for venue search queries
+static RESY_VENUESEARCH_PATH: &str = "/3/venuesearch/search";
+
+static RESY_FIND_PATH: &str = "/4/find";
+
+/// URL path to get a venue's details
+static RESY_VENUE_PATH: &str = "/3/venue";
+
+/// URL path to get a venue's reservation status for a range of days
+static RESY_CALENDAR_PATH: &str = "/4/venue/calendar";
+
+/// URL path to get reservation details
+static RESY_DETAILS_PATH: &str = "/3/details";
+
+/// URL path to book at
+static RESY_BOOK_PATH: &str = "/3/book";
+
+/// URL path for notify endpoints (data is sent either through query params or HTTP verbs)
+static RESY_NOTIFICATION_PATH: &str = "/3/notify";
 
-static RESY_FIND_URL: &str = "https://api.resy.com/4/find";
+/// URL path to exchange an email and password for an auth token
+static RESY_PASSWORD_AUTH_PATH: &str = "/3/auth/password";
 
-/// URL to get reservation details
-static RESY_DETAILS_URL: &str = "https://api.resy.com/3/details";
+/// URL path to get the user's account details
+static RESY_USER_PATH: &str = "/2/user";
 
-/// URL to book at
-static RESY_BOOK_URL: &str = "https://api.resy.com/3/book";
+/// URL path to list the user's reservations, upcoming or past is controlled through query params
+static RESY_USER_RESERVATIONS_PATH: &str = "/3/user/reservations";
 
-/// URL for notify endpoints (data is sent either through query params or HTTP verbs)
-static RESY_NOTIFICATION_URL: &str = "https://api.resy.com/3/notify";
+/// URL path to cancel a reservation at
+static RESY_CANCEL_PATH: &str = "/3/cancel";
+
+/// How often idle connections are pinged so they're still open when a drop comes around.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Number of reservations requested per page when listing reservations. Resy defaults to
+/// a small page size so ask for more than most people have.
+const RESY_RESERVATIONS_PAGE_SIZE: usize = 100;
 
 /// Header to send auth-token arg as
 static RESY_AUTH_TOKEN_HEADER: &str = "X-Resy-Auth-Token";
@@ -47,131 +99,625 @@ static RESY_AUTH_TOKEN_HEADER: &str = "X-Resy-Auth-Token";
 /// Date format Resy uses for sending/receiving dates in their objects.
 static RESY_DATE_FORMAT: &str = "%Y-%m-%d";
 
+/// Groups of Resy endpoints, used to configure how requests to each of them are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Config,
+    Search,
+    Find,
+    Venue,
+    Calendar,
+    Details,
+    Book,
+    Reservations,
+    Account,
+    Cancel,
+    Notifications,
+    Auth,
+}
+
+impl Endpoint {
+    /// Whether sending the same request twice has the same effect as sending it once.
+    /// Booking twice could end up with two reservations.
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(self, Endpoint::Book)
+    }
+}
+
+/// Email and password used to log in to Resy.
+#[derive(Clone)]
+struct Credentials {
+    email: String,
+    password: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("email", &self.email)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Builds the header value for an auth token, marking it sensitive so it isn't logged.
+fn auth_token_header(token: &str) -> Result<HeaderValue> {
+    let mut header = HeaderValue::from_str(token)
+        .map_err(|_| ResyError::InvalidConfig("auth token is not a valid header".into()))?;
+    header.set_sensitive(true);
+    Ok(header)
+}
+
 /// Client used for interacting with Resy. Under the hood, maintains
 /// a reqwst client
 #[derive(Debug)]
 pub struct ResyClient {
-    no_cache: bool,
+    /// Where city configs are cached, `None` when caching is disabled.
+    cache: Option<ConfigCache>,
+    cache_ttl: Duration,
+    /// Refresh of a stale config cache running in the background, see
+    /// [`ResyClient::finish_config_refresh`].
+    config_refresh: Option<JoinHandle<Result<Option<Vec<RestaurantCityConfig>>>>>,
+    /// Only accept restaurants whose name matches the name searched for exactly.
     strict_match: bool,
     client: Client,
+    /// Shared with tasks sending requests in the background.
+    session: Arc<Session>,
     restaurants: Vec<RestaurantCityConfig>,
 }
 
+/// Everything needed to send a request to Resy, kept apart from the client so requests
+/// can be sent from background tasks.
+#[derive(Debug)]
+struct Session {
+    client: Client,
+    base_url: String,
+    retry_policies: RetryPolicies,
+    rate_limiter: Option<RateLimiter>,
+    /// Token sent in the auth token header, replaced whenever the client logs in again.
+    auth_token: RwLock<Option<HeaderValue>>,
+    /// Number of times the client logged in, tells requests whether the token they were
+    /// rejected with has been replaced already.
+    logins: AtomicU64,
+    /// Held while logging in again so requests rejected together share one login.
+    login_lock: tokio::sync::Mutex<()>,
+    credentials: Option<Credentials>,
+}
+
+impl Session {
+    /// Builds the full URL for an API path using the configured base URL.
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Sends a request, logging in first if the client has credentials but no token yet.
+    /// If Resy rejects the token and the client has credentials, it logs in again and
+    /// resends the request once.
+    async fn send(&self, endpoint: Endpoint, request: RequestBuilder) -> Result<Response> {
+        // Read before sending so a login finishing while the request is out isn't missed
+        let logins = self.logins.load(Ordering::Acquire);
+        if self.credentials.is_some() && self.auth_token.read().unwrap().is_none() {
+            self.login_again(logins).await?;
+        }
+        let request = request.build()?;
+        let logins = self.logins.load(Ordering::Acquire);
+        match self.send_with_retries(endpoint, &request).await {
+            Err(ResyError::Unauthorized { .. }) if self.credentials.is_some() => {
+                self.login_again(logins).await?;
+                self.send_with_retries(endpoint, &request).await
+            }
+            result => result,
+        }
+    }
+
+    /// Logs in unless another request already did since the client had logged in `logins`
+    /// times, so concurrent requests needing a new token share a single login.
+    async fn login_again(&self, logins: u64) -> Result<()> {
+        let _login = self.login_lock.lock().await;
+        if self.logins.load(Ordering::Acquire) != logins {
+            return Ok(());
+        }
+        self.login().await
+    }
+
+    /// Sends a request, retrying it according to the endpoint's retry policy and waiting
+    /// on the rate limiter before every attempt. Responses with a non-success status are
+    /// turned into errors.
+    async fn send_with_retries(&self, endpoint: Endpoint, request: &Request) -> Result<Response> {
+        let policy = self.retry_policies.get(endpoint);
+        let mut attempt = 0;
+        loop {
+            // Every request body we send is in memory so cloning can't fail
+            let mut attempt_request = request
+                .try_clone()
+                .ok_or_else(|| ResyError::InvalidConfig("request can't be retried".into()))?;
+            // Read the token on every attempt in case another request refreshed it
+            if endpoint != Endpoint::Auth {
+                if let Some(token) = self.auth_token.read().unwrap().clone() {
+                    attempt_request
+                        .headers_mut()
+                        .insert(RESY_AUTH_TOKEN_HEADER, token);
+                }
+            }
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire(endpoint).await;
+            }
+            let result = match self.client.execute(attempt_request).await {
+                Ok(res) => check_response(res).await,
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Err(e) if attempt < policy.max_retries && e.is_retryable(endpoint) => {
+                    let Some(delay) = policy.delay(attempt, &e) else {
+                        return Err(e);
+                    };
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Exchanges the email and password the client was built with for a new auth token,
+    /// which is used for every request after this.
+    async fn login(&self) -> Result<()> {
+        let credentials = self.credentials.as_ref().ok_or_else(|| {
+            ResyError::InvalidConfig("no email and password to log in with".into())
+        })?;
+        let request = self
+            .client
+            .post(self.url(RESY_PASSWORD_AUTH_PATH))
+            .form(&[
+                ("email", &credentials.email),
+                ("password", &credentials.password),
+            ])
+            .build()?;
+        let text = self
+            .send_with_retries(Endpoint::Auth, &request)
+            .await?
+            .text()
+            .await?;
+        let auth: AuthResponse = serde_json::from_str(&text)?;
+        *self.auth_token.write().unwrap() = Some(auth_token_header(&auth.token)?);
+        self.logins.fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Fetches the city configs, returning the raw JSON along with the ETag Resy sent.
+    async fn get_configs_from_api(&self) -> Result<(String, Option<String>)> {
+        let res = self
+            .send(
+                Endpoint::Config,
+                self.client.get(self.url(RESY_CONFIG_PATH)),
+            )
+            .await?;
+        let etag = response_etag(&res);
+        Ok((res.text().await?, etag))
+    }
+
+    async fn fetch_into_cache(&self, cache: &ConfigCache) -> Result<Vec<RestaurantCityConfig>> {
+        let (results_json, etag) = self.get_configs_from_api().await?;
+        // Parse before writing so a bad response never ends up in the cache
+        let results: Vec<RestaurantCityConfig> = serde_json::from_str(&results_json)?;
+        cache.write(&results_json, etag)?;
+        Ok(results)
+    }
+
+    /// Sends a conditional request for the city configs, rewriting the cache if they
+    /// changed and marking it fresh if they didn't. Returns the new configs, `None` if they
+    /// haven't changed.
+    async fn refresh_stale_cache(
+        &self,
+        cache: &ConfigCache,
+        etag: Option<String>,
+    ) -> Result<Option<Vec<RestaurantCityConfig>>> {
+        let mut request = self.client.get(self.url(RESY_CONFIG_PATH));
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = self.send(Endpoint::Config, request).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            cache.touch()?;
+            return Ok(None);
+        }
+        let etag = response_etag(&response);
+        let results_json = response.text().await?;
+        let results: Vec<RestaurantCityConfig> = serde_json::from_str(&results_json)?;
+        cache.write(&results_json, etag)?;
+        Ok(Some(results))
+    }
+}
+
 impl ResyClient {
     pub fn builder() -> ResyClientBuilder {
         ResyClientBuilder::default()
     }
 
-    async fn get_configs_from_api(&mut self) -> anyhow::Result<String> {
-        let res = self
-            .client
-            .get(format!("{}{}", RESY_LOCATION_BASE, RESY_CONFIG_URL))
-            .send()
-            .await;
-        match res {
-            Ok(r) => {
-                let text = r.text().await?;
-                Ok(text)
-            }
-            Err(e) => Err(e.into()),
-        }
+    /// Builds the full URL for an API path using the configured base URL.
+    fn url(&self, path: &str) -> String {
+        self.session.url(path)
+    }
+
+    /// Sends a request, see [`Session::send`].
+    async fn send(&self, endpoint: Endpoint, request: RequestBuilder) -> Result<Response> {
+        self.session.send(endpoint, request).await
+    }
+
+    /// Exchanges the email and password the client was built with for a new auth token,
+    /// which is used for every request after this.
+    pub async fn login(&self) -> Result<()> {
+        self.session.login().await
+    }
+
+    /// The auth token currently in use, if the client has one.
+    pub fn auth_token(&self) -> Option<String> {
+        self.session
+            .auth_token
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|t| t.to_str().ok().map(str::to_owned))
     }
 
     /// Loads the restaurant city configs from Resy. This is all cities in the Resy network
-    /// that we can search for restaurants later.
-    pub async fn load_config(&mut self) -> anyhow::Result<()> {
-        if self.no_cache {
-            let results_json = self.get_configs_from_api().await?;
-            let results: Vec<RestaurantCityConfig> = serde_json::from_str(&results_json).unwrap();
+    /// that we can search for restaurants later. Configs are read from the cache when one
+    /// exists. A stale cache is still used right away while it's refreshed in the
+    /// background, Resy only sends the configs again if they changed. Call
+    /// [`ResyClient::finish_config_refresh`] to wait for the refresh and find out whether
+    /// it worked.
+    pub async fn load_config(&mut self) -> Result<()> {
+        let Some(cache) = self.cache.clone() else {
+            let (results_json, _) = self.session.get_configs_from_api().await?;
+            let results: Vec<RestaurantCityConfig> = serde_json::from_str(&results_json)?;
             self.restaurants.extend(results);
             return Ok(());
-        }
-        if let Some(proj_dirs) = ProjectDirs::from("xyz", "vec3d", "resy-reserver") {
-            fs::create_dir_all(proj_dirs.cache_dir()).expect("Unable to create cache dir");
-            //let results_json = self.load_configs_from_api().await?;
-            //let results :Vec<RestaurantCityConfig> = serde_json::from_str
-            let configs_path = proj_dirs.cache_dir().join("restaurants.json");
-            if configs_path.exists() {
-                let reader = BufReader::new(File::open(configs_path)?);
-                let results: Vec<RestaurantCityConfig> = serde_json::from_reader(reader)?;
+        };
+        // A cache that can't be parsed is treated the same as a missing one
+        let cached = cache.read()?.and_then(|(json, metadata)| {
+            serde_json::from_str::<Vec<RestaurantCityConfig>>(&json)
+                .ok()
+                .map(|results| (results, metadata))
+        });
+        match cached {
+            Some((results, metadata)) => {
+                if metadata.as_ref().is_none_or(|m| m.is_stale(self.cache_ttl)) {
+                    let session = Arc::clone(&self.session);
+                    let etag = metadata.and_then(|m| m.etag);
+                    self.config_refresh = Some(tokio::spawn(async move {
+                        session.refresh_stale_cache(&cache, etag).await
+                    }));
+                }
                 self.restaurants.extend(results);
-            } else {
-                let results_json = self.get_configs_from_api().await?;
-                fs::write(configs_path, &results_json).expect("Unable to save restaurant configs");
-                let results: Vec<RestaurantCityConfig> =
-                    serde_json::from_str(&results_json).unwrap();
+            }
+            None => {
+                let results = self.session.fetch_into_cache(&cache).await?;
                 self.restaurants.extend(results);
             }
         }
         Ok(())
     }
 
+    /// Waits for the background refresh started by [`ResyClient::load_config`], if there
+    /// is one, and switches to the refreshed configs when they changed. Returns the error
+    /// the refresh failed with, the cached configs stay in use when it does.
+    pub async fn finish_config_refresh(&mut self) -> Result<()> {
+        let Some(refresh) = self.config_refresh.take() else {
+            return Ok(());
+        };
+        let refreshed = refresh
+            .await
+            .map_err(|e| ResyError::Cache(std::io::Error::other(e)))??;
+        if let Some(results) = refreshed {
+            self.restaurants = results;
+        }
+        Ok(())
+    }
+
+    /// Fetches the city configs from Resy and rewrites the cache, no matter how old it is.
+    pub async fn refresh_config_cache(&mut self) -> Result<()> {
+        let cache = self
+            .cache
+            .clone()
+            .ok_or_else(|| ResyError::InvalidConfig("the config cache is disabled".into()))?;
+        self.restaurants = self.session.fetch_into_cache(&cache).await?;
+        Ok(())
+    }
+
     /// Gets the city configuration data for a given city so that we can search for the
-    /// restaurant later.
+    /// restaurant later. See [`city::find_city`] for how cities are matched.
     pub fn get_restaurant_city_config(
         &self,
         city: &str,
         country: &str,
-    ) -> Option<RestaurantCityConfig> {
-        for restaurant_city_config in self.restaurants.iter() {
-            if restaurant_city_config.is_match(city, country) {
-                return Some(restaurant_city_config.clone());
-            }
-        }
-        None
+    ) -> Result<RestaurantCityConfig> {
+        city::find_city(&self.restaurants, city, country).cloned()
     }
 
-    /// Tries to get the restaurant. Assumes the restaurant name provided is unique
-    /// to only have one restaurant in the given city.
+    /// Every city loaded by [`ResyClient::load_config`].
+    pub fn cities(&self) -> &[RestaurantCityConfig] {
+        &self.restaurants
+    }
+
+    /// Tries to get the restaurant, picking the hit whose name is closest to the name
+    /// provided. Several hits with exactly the same name (ignoring case and punctuation) is
+    /// an error since there's no telling which one was meant. With strict matching, only a
+    /// hit with exactly the same name is accepted.
     pub async fn find_restaurant_by_name(
         &self,
         city_config: &RestaurantCityConfig,
         name: &str,
-    ) -> anyhow::Result<Option<RestaurantSearchResult>> {
+    ) -> Result<Option<RestaurantSearchResult>> {
+        let ranked = self.find_restaurants_by_name(city_config, name).await?;
+        let exact_matches = ranked.iter().filter(|r| r.is_exact()).count();
+        if exact_matches > 1 {
+            return Err(ResyError::AmbiguousRestaurant {
+                name: name.to_owned(),
+                candidates: ranked
+                    .into_iter()
+                    .filter(|r| r.is_exact())
+                    .map(|r| r.restaurant)
+                    .collect(),
+            });
+        }
+        if self.strict_match && exact_matches == 0 {
+            return Ok(None);
+        }
+        // Exact matches are ranked first
+        Ok(ranked.into_iter().next().map(|r| r.restaurant))
+    }
+
+    /// Searches for restaurants in the city, returning every hit ranked by how closely
+    /// its name matches the name provided, closest first.
+    pub async fn find_restaurants_by_name(
+        &self,
+        city_config: &RestaurantCityConfig,
+        name: &str,
+    ) -> Result<Vec<RankedRestaurant>> {
         let geo_filter = GeoFilter::new(city_config.latitude, city_config.longitude, u16::MAX);
-        let restaurant_search_params = RestaurantSearchRequest::new(false, &geo_filter, name);
+        let hits = self
+            .search_restaurants_page(&SearchQuery::new(geo_filter, name), 1)
+            .await?
+            .hits;
 
-        let res = self
+        let query = matching::normalize(name);
+        let mut ranked: Vec<RankedRestaurant> = hits
+            .into_iter()
+            .map(|restaurant| RankedRestaurant {
+                similarity: matching::similarity(&matching::normalize(&restaurant.name), &query),
+                restaurant,
+            })
+            .collect();
+        // Stable sort so hits Resy ranked higher stay first among equally similar names
+        ranked.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        Ok(ranked)
+    }
+
+    /// Gets one page of the venues matching the search, pages start at 1. Hits that don't
+    /// pass the query's filters are left out so a page can have fewer hits than asked for,
+    /// while the page's totals still count the hits from before filtering.
+    pub async fn search_restaurants_page(
+        &self,
+        query: &SearchQuery,
+        page: u32,
+    ) -> Result<SearchPage> {
+        Ok(self.fetch_search_page(query, page).await?.0)
+    }
+
+    /// Gets a search page along with the ids of every hit on it, filtered or not.
+    async fn fetch_search_page(
+        &self,
+        query: &SearchQuery,
+        page: u32,
+    ) -> Result<(SearchPage, Vec<String>)> {
+        let restaurant_search_params =
+            RestaurantSearchRequest::new(false, &query.geo, &query.query)
+                .page(page, query.per_page);
+        let request = self
             .client
-            .post(format!(
-                "{}{}",
-                RESY_VENUESEARCH_BASE, RESY_VENUESEARCH_SEARCH
-            ))
-            .json(&restaurant_search_params)
-            .send()
-            .await?;
+            .post(self.url(RESY_VENUESEARCH_PATH))
+            .json(&restaurant_search_params);
+        let text = self.send(Endpoint::Search, request).await?.text().await?;
+        let value = serde_json::Value::from_str(&text)?;
+        let search = &value["search"];
+        let hits: Vec<RestaurantSearchResult> = serde_json::from_value(search["hits"].clone())?;
+        let ids = hits.iter().map(|hit| hit.object_id.clone()).collect();
+
+        let total_hits = search["nbHits"]
+            .as_u64()
+            .map_or(hits.len() as u32, |total| total as u32);
+        // Without a page count a full page means there may be more
+        let total_pages = match search["nbPages"].as_u64() {
+            Some(total) => total as u32,
+            None if hits.len() >= usize::from(query.per_page) => page + 1,
+            None => page,
+        };
+        let page = SearchPage {
+            hits: hits.into_iter().filter(|hit| query.matches(hit)).collect(),
+            page,
+            total_pages,
+            total_hits,
+        };
+        Ok((page, ids))
+    }
 
-        let text = res.text().await?;
-        let value = serde_json::Value::from_str(&text).unwrap();
-        let hits: Vec<RestaurantSearchResult> =
-            serde_json::from_value::<Vec<RestaurantSearchResult>>(value["search"]["hits"].clone())
-                .unwrap();
-        Ok(hits.first().cloned())
+    /// Goes through every venue matching the search in the order Resy ranks them. Pages
+    /// are only requested once the hits of the previous page have been used up. Paging
+    /// stops early at an empty page or one that repeats the previous page, which is what
+    /// a server ignoring the page asked for sends back.
+    pub fn search_restaurants_stream<'a>(
+        &'a self,
+        query: &'a SearchQuery,
+    ) -> impl Stream<Item = Result<RestaurantSearchResult>> + 'a {
+        stream::try_unfold(Some((1, Vec::new())), move |state| async move {
+            let Some((page, previous_ids)) = state else {
+                return Ok::<_, ResyError>(None);
+            };
+            let (results, ids) = self.fetch_search_page(query, page).await?;
+            if ids == previous_ids {
+                return Ok(None);
+            }
+            let next = (results.has_next() && !ids.is_empty()).then_some((page + 1, ids));
+            Ok(Some((stream::iter(results.hits.into_iter().map(Ok)), next)))
+        })
+        .try_flatten()
+    }
+
+    /// Gets every venue matching the search, in the query's sort order.
+    pub async fn search_restaurants(
+        &self,
+        query: &SearchQuery,
+    ) -> Result<Vec<RestaurantSearchResult>> {
+        let mut hits: Vec<RestaurantSearchResult> =
+            self.search_restaurants_stream(query).try_collect().await?;
+        query.sort(&mut hits);
+        Ok(hits)
+    }
+
+    /// Finds venues in an area with open tables for the party on the day. Only slots in
+    /// the query's time window are kept and venues left without any are dropped, the
+    /// rest stay in the order Resy ranked them. Resy doesn't know about the window, so
+    /// pages keep being fetched until the limit is met or the venues run out.
+    pub async fn discover(&self, query: &DiscoverQuery) -> Result<Vec<DiscoveredVenue>> {
+        let limit = usize::from(query.limit);
+        let mut venues = Vec::new();
+        let mut seen = HashSet::new();
+        let mut page = 1;
+        loop {
+            let request = self
+                .client
+                .post(self.url(RESY_VENUESEARCH_PATH))
+                .json(&RestaurantSearchRequest::discover(query).page(page, query.limit));
+            let text = self.send(Endpoint::Search, request).await?.text().await?;
+            let value = serde_json::Value::from_str(&text)?;
+            let search = &value["search"];
+            let hits = match &search["hits"] {
+                serde_json::Value::Array(hits) => hits.as_slice(),
+                _ => &[],
+            };
+
+            let mut new_hits = 0;
+            for hit in hits {
+                let restaurant: RestaurantSearchResult = serde_json::from_value(hit.clone())?;
+                if !seen.insert(restaurant.object_id.clone()) {
+                    continue;
+                }
+                new_hits += 1;
+                let slots: Vec<ReservationSlot> = match &hit["availability"]["slots"] {
+                    serde_json::Value::Null => Vec::new(),
+                    slots => serde_json::from_value(slots.clone())?,
+                };
+                let slots: Vec<ReservationSlot> =
+                    slots.into_iter().filter(|s| query.matches(s)).collect();
+                if !slots.is_empty() {
+                    venues.push(DiscoveredVenue { restaurant, slots });
+                }
+            }
+
+            // A page of venues already seen means the server ignores paging
+            let last_page = match search["nbPages"].as_u64() {
+                Some(total) => u64::from(page) >= total,
+                None => hits.len() < limit,
+            };
+            if venues.len() >= limit || last_page || new_hits == 0 {
+                break;
+            }
+            page += 1;
+        }
+        venues.truncate(limit);
+        Ok(venues)
     }
 
     /// Gets reservations for a given restaurant. Empty vec means no time slots on
     /// the given date were found.
     pub async fn get_reservations(
         &self,
-        restaurant_id: &String,
+        restaurant_id: &str,
         date: &NaiveDate,
         party_size: u8,
-    ) -> anyhow::Result<Vec<ReservationSlot>> {
-        let res = self
+    ) -> Result<Vec<ReservationSlot>> {
+        // Only the slots are parsed so changes to the venue's details can't get in the way
+        let venue = self.find_venue(restaurant_id, date, party_size).await?;
+        match &venue["slots"] {
+            serde_json::Value::Null => Ok(Vec::new()),
+            slots => Ok(serde_json::from_value(slots.clone())?),
+        }
+    }
+
+    /// Gets a restaurant's details along with the slots it has open on the given date.
+    pub async fn get_venue_availability(
+        &self,
+        restaurant_id: &str,
+        date: &NaiveDate,
+        party_size: u8,
+    ) -> Result<VenueAvailability> {
+        let venue = self.find_venue(restaurant_id, date, party_size).await?;
+        Ok(serde_json::from_value(venue)?)
+    }
+
+    /// Gets the unparsed venue the find endpoint returns for a restaurant on a date.
+    async fn find_venue(
+        &self,
+        restaurant_id: &str,
+        date: &NaiveDate,
+        party_size: u8,
+    ) -> Result<serde_json::Value> {
+        let request = self
             .client
-            .get(RESY_FIND_URL)
+            .get(self.url(RESY_FIND_PATH))
             .query(&[("lat", "0")])
             .query(&[("long", "0")])
             .query(&[("venue_id", restaurant_id)])
             .query(&[("day", date.format(RESY_DATE_FORMAT).to_string())])
-            .query(&[("party_size", &party_size.to_string())])
-            .send()
-            .await?;
-        let text = res.text().await?;
-        let value = serde_json::Value::from_str(&text).unwrap();
-        let slots_value = value["results"]["venues"][0]["slots"].clone();
-        let slots = serde_json::from_value(slots_value).unwrap();
-        Ok(slots)
+            .query(&[("party_size", &party_size.to_string())]);
+        let text = self.send(Endpoint::Find, request).await?.text().await?;
+        let mut value = serde_json::Value::from_str(&text)?;
+        let venue = value["results"]["venues"][0].take();
+        if venue.is_null() {
+            return Err(ResyError::VenueNotFound(restaurant_id.to_owned()));
+        }
+        Ok(venue)
+    }
+
+    /// Gets a restaurant's details without looking up any slots.
+    pub async fn get_venue(&self, restaurant_id: &str) -> Result<Venue> {
+        let request = self
+            .client
+            .get(self.url(RESY_VENUE_PATH))
+            .query(&[("id", restaurant_id)]);
+        let response = match self.send(Endpoint::Venue, request).await {
+            Err(ResyError::Api { status: 404, .. }) => {
+                return Err(ResyError::VenueNotFound(restaurant_id.to_owned()))
+            }
+            response => response?,
+        };
+        Ok(serde_json::from_str(&response.text().await?)?)
+    }
+
+    /// Gets whether a restaurant has slots open for the party on each day from `start` to
+    /// `end`, both included. One request covers the whole range, unlike checking each day
+    /// with [`ResyClient::get_reservations`].
+    pub async fn get_availability_calendar(
+        &self,
+        restaurant_id: &str,
+        party_size: u8,
+        start: &NaiveDate,
+        end: &NaiveDate,
+    ) -> Result<AvailabilityCalendar> {
+        let request = self
+            .client
+            .get(self.url(RESY_CALENDAR_PATH))
+            .query(&[("venue_id", restaurant_id)])
+            .query(&[("num_seats", &party_size.to_string())])
+            .query(&[("start_date", start.format(RESY_DATE_FORMAT).to_string())])
+            .query(&[("end_date", end.format(RESY_DATE_FORMAT).to_string())]);
+        let response = match self.send(Endpoint::Calendar, request).await {
+            Err(ResyError::Api { status: 404, .. }) => {
+                return Err(ResyError::VenueNotFound(restaurant_id.to_owned()))
+            }
+            response => response?,
+        };
+        Ok(serde_json::from_str(&response.text().await?)?)
     }
 
     /// Retrieves the reservation details for a slot.
@@ -180,33 +726,49 @@ impl ResyClient {
         reservation_slot: &ReservationSlot,
         date: &NaiveDate,
         party_size: u8,
-    ) -> anyhow::Result<ReservationDetails> {
+    ) -> Result<ReservationDetails> {
         let details_request = ReservationDetailsRequest::new(
             reservation_slot.config.token.clone(),
             date.format(RESY_DATE_FORMAT).to_string(),
             party_size.to_string(),
         );
-        let res = self
+        self.send_details_request(&details_request).await
+    }
+
+    /// Retrieves the reservation details for the slot a token describes, without
+    /// needing to find the slot first.
+    pub async fn get_reservation_details_for_token(
+        &self,
+        token: &RgsToken,
+    ) -> Result<ReservationDetails> {
+        self.send_details_request(&token.details_request()).await
+    }
+
+    async fn send_details_request(
+        &self,
+        details_request: &ReservationDetailsRequest,
+    ) -> Result<ReservationDetails> {
+        let request = self
             .client
-            .post(RESY_DETAILS_URL)
-            .json(&details_request)
-            .send()
-            .await?
-            .json()
-            .await?;
-        Ok(res)
+            .post(self.url(RESY_DETAILS_PATH))
+            .json(details_request);
+        let text = self.send(Endpoint::Details, request).await?.text().await?;
+        Ok(serde_json::from_str(&text)?)
     }
 
-    /// Makes a booking request with Resy using the book token and payment method.
-    /// TODO: Investigate ways this can actually fail
+    /// Makes a booking request with Resy using the book token from the reservation details
+    /// and the payment method. `specs` describe the slot the details are for and fill out
+    /// the confirmation. If the slot was taken in the meantime this returns
+    /// [`ResyError::BookingConflict`].
     pub async fn book_restaurant(
         &self,
-        book_token: &BookToken,
+        reservation_details: &ReservationDetails,
+        specs: &BookingSpecs,
         payment: &PaymentMethod,
-    ) -> anyhow::Result<()> {
+    ) -> Result<BookingConfirmation> {
         // Build the form data for the booking request
         let mut params = HashMap::new();
-        params.insert("book_token", book_token.value.clone());
+        params.insert("book_token", reservation_details.book_token.value.clone());
         params.insert(
             "struct_payment_method",
             format!("{{\"id\":{}}}", payment.id),
@@ -214,23 +776,144 @@ impl ResyClient {
         params.insert("venute_marketing_opt_in", "0".to_string());
         params.insert("source_id", "resy.com-venue-details".to_string());
 
-        let res = self.client.post(RESY_BOOK_URL).form(&params).send().await;
+        let request = self.client.post(self.url(RESY_BOOK_PATH)).form(&params);
+        let text = self
+            .send(Endpoint::Book, request)
+            .await
+            .map_err(ResyError::into_booking_error)?
+            .text()
+            .await?;
+        let booking: BookResponse = serde_json::from_str(&text)?;
+        Ok(BookingConfirmation {
+            reservation_id: booking.reservation_id,
+            resy_token: booking.resy_token,
+            venue_id: specs.venue_id.clone(),
+            date: specs.date,
+            time: specs.time,
+            party_size: specs.party_size,
+        })
+    }
+
+    /// Gets the cards the user has on file.
+    pub async fn get_payment_methods(&self) -> Result<Vec<PaymentMethod>> {
+        let request = self.client.get(self.url(RESY_USER_PATH));
+        let text = self.send(Endpoint::Account, request).await?.text().await?;
+        let account: UserAccount = serde_json::from_str(&text)?;
+        Ok(account.payment_methods)
+    }
+
+    /// Checks that the user has a card matching the selector, so a missing card is noticed
+    /// before a drop instead of when booking.
+    pub async fn check_payment_method(&self, selector: &PaymentSelector) -> Result<PaymentMethod> {
+        selector.select(&self.get_payment_methods().await?).cloned()
+    }
 
-        match res {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.into()),
+    /// Opens `connections` connections to Resy ahead of time by sending that many requests
+    /// at once. The client keeps them in its pool so requests made shortly after don't
+    /// wait on DNS, TCP and TLS setup. Over HTTP/2 every request shares one connection.
+    /// The requests count against the rate limiter's budget for [`Endpoint::Config`].
+    pub async fn warm_up(&self, connections: usize) -> Result<()> {
+        let requests = (0..connections.max(1)).map(|_| async {
+            if let Some(rate_limiter) = &self.session.rate_limiter {
+                rate_limiter.acquire(Endpoint::Config).await;
+            }
+            self.client.head(self.url(RESY_CONFIG_PATH)).send().await
+        });
+        // Only the connection matters, whatever status Resy answers with
+        for response in future::join_all(requests).await {
+            response?;
         }
+        Ok(())
     }
 
-    pub async fn get_notifications(&self) -> anyhow::Result<Vec<ResyNotification>> {
-        let response = self.client.get(RESY_NOTIFICATION_URL).send().await;
-        match response {
-            Ok(r) => {
-                let json: ResyNotificationResults = r.json().await?;
-                Ok(json.notify)
+    /// Measures how far Resy's clock is from the local one using the Date headers of
+    /// `samples` requests. The requests are spread out over about a second so the headers
+    /// tick over at different points, see [`ClockOffset::estimate`].
+    pub async fn measure_clock_offset(&self, samples: u32) -> Result<ClockOffset> {
+        let spacing = Duration::from_millis(1000 / u64::from(samples.max(1)));
+        let mut measured = Vec::new();
+        for i in 0..samples {
+            if i > 0 {
+                tokio::time::sleep(spacing).await;
             }
-            Err(e) => Err(e.into()),
+            let sent = Utc::now();
+            let response = self
+                .send(Endpoint::Account, self.client.get(self.url(RESY_USER_PATH)))
+                .await?;
+            let received = Utc::now();
+            let sample = ClockSample::from_response(&response, sent, received)
+                .ok_or(ResyError::NoServerDate)?;
+            measured.push(sample);
         }
+        ClockOffset::estimate(&measured).ok_or(ResyError::NoServerDate)
+    }
+
+    /// Gets the reservations the user has coming up.
+    pub async fn get_upcoming_reservations(&self) -> Result<Vec<UserReservation>> {
+        self.get_user_reservations("upcoming").await
+    }
+
+    /// Gets the reservations the user has already been to (or cancelled.)
+    pub async fn get_past_reservations(&self) -> Result<Vec<UserReservation>> {
+        self.get_user_reservations("past").await
+    }
+
+    /// Lists the reservations page by page until a page comes back short. A page without
+    /// any new reservations also ends the listing, in case Resy ignores the offset.
+    async fn get_user_reservations(&self, reservation_type: &str) -> Result<Vec<UserReservation>> {
+        let mut reservations: Vec<UserReservation> = Vec::new();
+        let mut seen = HashSet::new();
+        loop {
+            let request = self
+                .client
+                .get(self.url(RESY_USER_RESERVATIONS_PATH))
+                .query(&[("type", reservation_type)])
+                .query(&[("limit", RESY_RESERVATIONS_PAGE_SIZE)])
+                // Offsets start at 1
+                .query(&[("offset", reservations.len() + 1)]);
+            let text = self
+                .send(Endpoint::Reservations, request)
+                .await?
+                .text()
+                .await?;
+            let response: UserReservationsResponse = serde_json::from_str(&text)?;
+            let page_size = response.reservations.len();
+            let venues = response.venues;
+            let found = reservations.len();
+            reservations.extend(
+                response
+                    .reservations
+                    .into_iter()
+                    .filter(|r| seen.insert(r.reservation_id))
+                    .map(|mut r| {
+                        r.venue.name = venues.get(&r.venue.id.to_string()).map(|v| v.name.clone());
+                        r
+                    }),
+            );
+            if page_size < RESY_RESERVATIONS_PAGE_SIZE || reservations.len() == found {
+                return Ok(reservations);
+            }
+        }
+    }
+
+    /// Cancels a reservation using the resy_token returned when it was booked.
+    pub async fn cancel_reservation(&self, resy_token: &str) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("resy_token", resy_token);
+        let request = self.client.post(self.url(RESY_CANCEL_PATH)).form(&params);
+        self.send(Endpoint::Cancel, request).await?;
+        Ok(())
+    }
+
+    pub async fn get_notifications(&self) -> Result<Vec<ResyNotification>> {
+        let request = self.client.get(self.url(RESY_NOTIFICATION_PATH));
+        let text = self
+            .send(Endpoint::Notifications, request)
+            .await?
+            .text()
+            .await?;
+        let json: ResyNotificationResults = serde_json::from_str(&text)?;
+        Ok(json.notify)
     }
 
     /// Creates or updates notifications. Resy does not support the UPDATE HTTP verb and
@@ -238,41 +921,30 @@ impl ResyClient {
     /// a notification but with whatever the new fields are supposed to be. It seems like
     /// you can only have one notification for a day/restaurant at a time, their backend
     /// handles the de-duplication.
-    pub async fn create_notification(&self, notification: &ResyNotification) -> anyhow::Result<()> {
+    pub async fn create_notification(&self, notification: &ResyNotification) -> Result<()> {
         let mut params = HashMap::new();
-        params.insert(
-            "struct_data",
-            serde_json::to_string(notification).expect("Unable to serialize notification json"),
-        );
-        let response = self
+        params.insert("struct_data", serde_json::to_string(notification)?);
+        let request = self
             .client
-            .post(RESY_NOTIFICATION_URL)
-            .form(&params)
-            .send()
-            .await;
-        match response {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.into()),
-        }
+            .post(self.url(RESY_NOTIFICATION_PATH))
+            .form(&params);
+        self.send(Endpoint::Notifications, request).await?;
+        Ok(())
     }
 
-    pub async fn delete_notification(&self, notification: &ResyNotification) -> anyhow::Result<()> {
-        let response = self
+    pub async fn delete_notification(&self, notification: &ResyNotification) -> Result<()> {
+        let request = self
             .client
-            .delete(RESY_NOTIFICATION_URL)
-            .query(&["venue_id", &notification.specs.venue_id.to_string()])
-            .query(&["day", &notification.specs.day])
-            .query(&["num_seats", &notification.specs.party_size.to_string()])
-            .query(&[
+            .delete(self.url(RESY_NOTIFICATION_PATH))
+            .query(&[("venue_id", &notification.specs.venue_id.to_string())])
+            .query(&[("day", &notification.specs.day)])
+            .query(&[("num_seats", &notification.specs.party_size.to_string())])
+            .query(&[(
                 "service_type_id",
                 &notification.specs.service_type_id.to_string(),
-            ])
-            .send()
-            .await;
-        match response {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e.into()),
-        }
+            )]);
+        self.send(Endpoint::Notifications, request).await?;
+        Ok(())
     }
 }
 
@@ -280,8 +952,14 @@ impl ResyClient {
 pub struct ResyClientBuilder {
     api_key: String,
     auth_key: String,
+    credentials: Option<Credentials>,
     no_cache: bool,
+    cache_dir: Option<PathBuf>,
+    cache_ttl: Option<Duration>,
     strict_match: bool,
+    base_url: Option<String>,
+    retry_policies: RetryPolicies,
+    rate_limiter: Option<RateLimiter>,
 }
 
 impl ResyClientBuilder {
@@ -289,45 +967,135 @@ impl ResyClientBuilder {
         ResyClientBuilder {
             api_key,
             auth_key,
+            credentials: None,
             no_cache: false,
+            cache_dir: None,
+            cache_ttl: None,
             strict_match: false,
+            base_url: None,
+            retry_policies: RetryPolicies::default(),
+            rate_limiter: None,
         }
     }
 
+    /// Logs in with an email and password instead of (or to refresh) the auth token. The
+    /// client logs in before its first request if it wasn't given an auth token and again
+    /// whenever Resy rejects the token.
+    pub fn credentials(mut self, email: String, password: String) -> ResyClientBuilder {
+        self.credentials = Some(Credentials { email, password });
+        self
+    }
+
     pub fn no_cache(mut self) -> ResyClientBuilder {
         self.no_cache = true;
         self
     }
 
+    /// Caches city configs in the given directory instead of the user's cache directory.
+    /// Configs are only cached for a custom base URL when a directory is set.
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> ResyClientBuilder {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// How long cached city configs are used before being refreshed (defaults to
+    /// [`DEFAULT_CACHE_TTL`].)
+    pub fn cache_ttl(mut self, ttl: Duration) -> ResyClientBuilder {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
     pub fn strict_match(mut self) -> ResyClientBuilder {
         self.strict_match = true;
         self
     }
 
-    pub fn build(self) -> ResyClient {
+    /// Overrides the base URL every endpoint is requested from (defaults to
+    /// [`RESY_API_BASE`].) Useful for running against a local mock of the Resy API.
+    pub fn base_url(mut self, base_url: &str) -> ResyClientBuilder {
+        self.base_url = Some(base_url.trim_end_matches('/').to_owned());
+        self
+    }
+
+    /// Sets the retry policy used by every endpoint without its own policy. Bookings are
+    /// only ever retried when Resy is known to not have processed the request.
+    pub fn default_retry_policy(mut self, policy: RetryPolicy) -> ResyClientBuilder {
+        self.retry_policies.default = policy;
+        self
+    }
+
+    /// Sets the retry policy for a single endpoint, overriding the default policy.
+    pub fn retry_policy(mut self, endpoint: Endpoint, policy: RetryPolicy) -> ResyClientBuilder {
+        self.retry_policies.endpoints.insert(endpoint, policy);
+        self
+    }
+
+    /// Limits the rate of requests sent by the client. Pass clones of the same limiter to
+    /// several clients to share one budget between them.
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> ResyClientBuilder {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    pub fn build(self) -> Result<ResyClient> {
         let mut headers = HeaderMap::new();
 
         let mut api_header =
             HeaderValue::from_str(&format!("ResyAPI api_key=\"{}\"", self.api_key))
-                .expect("Key invalid HTTP header value");
+                .map_err(|_| ResyError::InvalidConfig("api key is not a valid header".into()))?;
         api_header.set_sensitive(true);
 
-        let mut auth_key_header =
-            HeaderValue::from_str(&self.auth_key).expect("Key invalid HTTP header value");
-        auth_key_header.set_sensitive(true);
+        // An empty token means the client will log in with credentials instead
+        let auth_token = if self.auth_key.is_empty() {
+            None
+        } else {
+            Some(auth_token_header(&self.auth_key)?)
+        };
 
         headers.insert(AUTHORIZATION, api_header);
-        headers.insert(RESY_AUTH_TOKEN_HEADER, auth_key_header);
         headers.insert("User-Agent", HeaderValue::from_static(USER_AGENT));
 
-        ResyClient {
-            no_cache: self.no_cache,
+        let base_url = self.base_url.unwrap_or_else(|| RESY_API_BASE.to_owned());
+        let cache = match self.cache_dir {
+            _ if self.no_cache => None,
+            Some(dir) => Some(ConfigCache::new(dir)),
+            None => ConfigCache::for_base_url(&base_url),
+        };
+
+        // HTTP/2 is negotiated when Resy supports it, keep idle connections alive either
+        // way so warmed up connections are still there when they're needed
+        let client = Client::builder()
+            .default_headers(headers)
+            .tcp_keepalive(KEEPALIVE_INTERVAL)
+            .http2_keep_alive_interval(KEEPALIVE_INTERVAL)
+            .http2_keep_alive_while_idle(true)
+            .build()?;
+
+        Ok(ResyClient {
+            cache,
+            cache_ttl: self.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL),
+            config_refresh: None,
             strict_match: self.strict_match,
-            client: Client::builder()
-                .default_headers(headers)
-                .build()
-                .expect("Unable to construct HTTP client"),
+            client: client.clone(),
+            session: Arc::new(Session {
+                client,
+                base_url,
+                retry_policies: self.retry_policies,
+                rate_limiter: self.rate_limiter,
+                auth_token: RwLock::new(auth_token),
+                logins: AtomicU64::new(0),
+                login_lock: tokio::sync::Mutex::new(()),
+                credentials: self.credentials,
+            }),
             restaurants: Vec::<RestaurantCityConfig>::new(),
-        }
+        })
     }
 }
+
+fn response_etag(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_owned)
+}
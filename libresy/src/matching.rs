@@ -0,0 +1,71 @@
+/// Lowercases a name and treats hyphens, underscores and runs of whitespace the same so
+/// that "New York", "new-york" and "new_york" compare equal. Other punctuation is dropped.
+pub(crate) fn normalize(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Levenshtein distance between two strings, counted in chars.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Whether the normalized query shows up as whole words in the normalized name, e.g.
+/// "york" in "new york ny".
+pub(crate) fn contains_words(name: &str, query: &str) -> bool {
+    format!(" {name} ").contains(&format!(" {query} "))
+}
+
+/// Whether the normalized query starts at a word boundary in the normalized name, e.g.
+/// "phila" in "philadelphia pa".
+pub(crate) fn contains_word_prefix(name: &str, query: &str) -> bool {
+    format!(" {name}").contains(&format!(" {query}"))
+}
+
+/// How similar two normalized names are, from 0.0 to 1.0 for names that are the same.
+/// Names that contain the query as whole words rank above names that are merely close
+/// in spelling, so "stella" is closer to "pizzeria stella" than to "stellar bar".
+pub(crate) fn similarity(name: &str, query: &str) -> f32 {
+    let len = name.chars().count().max(query.chars().count());
+    if len == 0 {
+        return 1.0;
+    }
+    let spelling = 1.0 - edit_distance(name, query) as f32 / len as f32;
+    if contains_words(name, query) {
+        let coverage = query.chars().count() as f32 / len as f32;
+        return spelling.max(0.5 + 0.5 * coverage);
+    }
+    spelling
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching() {
+        assert_eq!(normalize(" New_York - NY "), "new york ny");
+        assert_eq!(normalize("L'Artusi"), "lartusi");
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(similarity("lucali", "lucali"), 1.0);
+        assert!(similarity("pizzeria stella", "stella") > similarity("stellar bar", "stella"));
+    }
+}
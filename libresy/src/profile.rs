@@ -0,0 +1,301 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use directories::ProjectDirs;
+use serde::Deserialize;
+
+use crate::{
+    error::{Result, ResyError},
+    ResyClientBuilder,
+};
+
+/// Keyring service secrets are stored under. The username of each entry is
+/// `<profile>/<field>`, e.g. `work/password`.
+#[cfg_attr(not(feature = "keyring"), allow(dead_code))]
+static KEYRING_SERVICE: &str = "resy-reserver";
+
+/// A named set of credentials, read from profiles.toml. Every field is optional so that a
+/// profile can be combined with values given on the command line, see [`Profile::or`].
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub api_key: Option<String>,
+    pub auth_token: Option<String>,
+    pub email: Option<String>,
+    pub password: Option<String>,
+    pub base_url: Option<String>,
+    /// Read the api key, auth token and password from the Secret Service keyring when
+    /// they aren't set in the file.
+    #[serde(default)]
+    pub keyring: bool,
+}
+
+impl fmt::Debug for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redacted = |secret: &Option<String>| secret.as_ref().map(|_| "<redacted>");
+        f.debug_struct("Profile")
+            .field("api_key", &redacted(&self.api_key))
+            .field("auth_token", &redacted(&self.auth_token))
+            .field("email", &self.email)
+            .field("password", &redacted(&self.password))
+            .field("base_url", &self.base_url)
+            .field("keyring", &self.keyring)
+            .finish()
+    }
+}
+
+impl Profile {
+    /// Fills in the fields that aren't set on this profile from `fallback`. The fallback's
+    /// password is only used along with its own email, never for another account's.
+    pub fn or(self, fallback: Profile) -> Profile {
+        let password = if self.uses_email_of(&fallback) {
+            self.password.or(fallback.password)
+        } else {
+            self.password
+        };
+        Profile {
+            api_key: self.api_key.or(fallback.api_key),
+            auth_token: self.auth_token.or(fallback.auth_token),
+            email: self.email.or(fallback.email),
+            password,
+            base_url: self.base_url.or(fallback.base_url),
+            keyring: self.keyring || fallback.keyring,
+        }
+    }
+
+    /// Whether merging with `other` keeps `other`'s email, so its password goes with it.
+    fn uses_email_of(&self, other: &Profile) -> bool {
+        self.email.is_none() || self.email == other.email
+    }
+
+    /// Creates a client builder with the profile's credentials. An api key is required
+    /// along with either an auth token or an email and password to log in with.
+    pub fn client_builder(&self) -> Result<ResyClientBuilder> {
+        let api_key = self
+            .api_key
+            .clone()
+            .ok_or_else(|| ResyError::Profile("no api key provided".into()))?;
+        let mut builder =
+            ResyClientBuilder::new(api_key, self.auth_token.clone().unwrap_or_default());
+        match (&self.email, &self.password) {
+            (Some(email), Some(password)) => {
+                builder = builder.credentials(email.clone(), password.clone());
+            }
+            (Some(email), None) => {
+                return Err(ResyError::Profile(format!(
+                    "no password provided for {email}"
+                )));
+            }
+            (None, _) if self.auth_token.is_none() => {
+                return Err(ResyError::Profile("no auth token or email provided".into()));
+            }
+            (None, _) => {}
+        }
+        if let Some(base_url) = &self.base_url {
+            builder = builder.base_url(base_url);
+        }
+        Ok(builder)
+    }
+
+    /// Looks up the secrets that aren't set on the profile in the keyring. The password
+    /// is only looked up when the email is the one stored with it.
+    #[cfg(feature = "keyring")]
+    fn with_keyring_secrets(mut self, name: &str, lookup_password: bool) -> Result<Profile> {
+        let lookup = |field: &str| -> Result<Option<String>> {
+            let entry = keyring::Entry::new(KEYRING_SERVICE, &format!("{name}/{field}"))
+                .map_err(|e| ResyError::Profile(format!("keyring error: {e}")))?;
+            match entry.get_password() {
+                Ok(secret) => Ok(Some(secret)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(ResyError::Profile(format!(
+                    "unable to read {name}/{field} from the keyring: {e}"
+                ))),
+            }
+        };
+        if self.api_key.is_none() {
+            self.api_key = lookup("api_key")?;
+        }
+        if self.auth_token.is_none() {
+            self.auth_token = lookup("auth_token")?;
+        }
+        if lookup_password && self.email.is_some() && self.password.is_none() {
+            self.password = lookup("password")?;
+        }
+        Ok(self)
+    }
+
+    #[cfg(not(feature = "keyring"))]
+    fn with_keyring_secrets(self, _name: &str, _lookup_password: bool) -> Result<Profile> {
+        Err(ResyError::Profile(
+            "libresy was built without keyring support".into(),
+        ))
+    }
+}
+
+/// Profiles stored in a TOML file, one table per profile:
+///
+/// ```toml
+/// [work]
+/// api_key = "..."
+/// email = "me@work.example"
+/// keyring = true
+/// ```
+#[derive(Debug, Default)]
+pub struct Profiles {
+    path: PathBuf,
+    profiles: HashMap<String, Profile>,
+}
+
+impl Profiles {
+    /// Location of profiles.toml in the user's config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        ProjectDirs::from("xyz", "vec3d", "resy-reserver")
+            .map(|proj_dirs| proj_dirs.config_dir().join("profiles.toml"))
+    }
+
+    /// Loads the profiles from the default location. No profiles are loaded if the file
+    /// doesn't exist.
+    pub fn load() -> Result<Profiles> {
+        let path = Profiles::default_path()
+            .ok_or_else(|| ResyError::Profile("unable to find the config directory".into()))?;
+        Profiles::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Profiles> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(ResyError::Profile(format!(
+                    "unable to read {}: {e}",
+                    path.display()
+                )))
+            }
+        };
+        Profiles::parse(path, &contents)
+    }
+
+    fn parse(path: &Path, contents: &str) -> Result<Profiles> {
+        let profiles = toml::from_str(contents)
+            .map_err(|e| ResyError::Profile(format!("unable to parse {}: {e}", path.display())))?;
+        Ok(Profiles {
+            path: path.to_owned(),
+            profiles,
+        })
+    }
+
+    /// Names of every stored profile, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Gets a profile by name as stored in the file, without any secrets from the keyring.
+    pub fn get(&self, name: &str) -> Result<Profile> {
+        self.profiles.get(name).cloned().ok_or_else(|| {
+            ResyError::Profile(format!(
+                "no profile named {name} in {}",
+                self.path.display()
+            ))
+        })
+    }
+
+    /// Combines credentials given as flags with the named profile's, the flags taking
+    /// priority. Secrets still missing afterwards are read from the keyring if the profile
+    /// uses it. The profile's password, stored or in the keyring, is only used for the
+    /// profile's email, an email given as a flag needs its password given as a flag too.
+    pub fn resolve(&self, flags: Profile, name: &str) -> Result<Profile> {
+        let stored = self.get(name)?;
+        let lookup_password = flags.uses_email_of(&stored);
+        let profile = flags.or(stored);
+        if profile.keyring {
+            return profile.with_keyring_secrets(name, lookup_password);
+        }
+        Ok(profile)
+    }
+}
+
+/// Creates a client builder from credentials given as flags, filling in the rest from the
+/// named profile if there is one, see [`Profiles::resolve`].
+pub fn client_builder(flags: Profile, profile: Option<&str>) -> Result<ResyClientBuilder> {
+    let profile = match profile {
+        Some(name) => Profiles::load()?.resolve(flags, name)?,
+        None => flags,
+    };
+    profile.client_builder()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        let profiles = Profiles::parse(
+            Path::new("profiles.toml"),
+            r#"
+            [work]
+            api_key = "work-key"
+            email = "me@work.example"
+            password = "hunter2"
+
+            [personal]
+            api_key = "personal-key"
+            auth_token = "personal-token"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(profiles.names(), vec!["personal", "work"]);
+        assert!(profiles.get("missing").is_err());
+
+        let work = profiles.get("work").unwrap();
+        assert!(work.client_builder().is_ok());
+        assert!(!format!("{work:?}").contains("hunter2"));
+
+        // Values from the command line take priority over the profile
+        let flags = Profile {
+            auth_token: Some("flag-token".to_owned()),
+            ..Profile::default()
+        };
+        let personal = profiles.resolve(flags, "personal").unwrap();
+        assert_eq!(personal.api_key.as_deref(), Some("personal-key"));
+        assert_eq!(personal.auth_token.as_deref(), Some("flag-token"));
+
+        let no_password = Profile {
+            api_key: Some("key".to_owned()),
+            email: Some("me@work.example".to_owned()),
+            ..Profile::default()
+        };
+        assert!(no_password.client_builder().is_err());
+
+        // The profile's password is never sent along with another account's email
+        let flags = Profile {
+            email: Some("me@home.example".to_owned()),
+            ..Profile::default()
+        };
+        let work = profiles.resolve(flags, "work").unwrap();
+        assert_eq!(work.email.as_deref(), Some("me@home.example"));
+        assert_eq!(work.password, None);
+        assert!(work.client_builder().is_err());
+        let flags = Profile {
+            email: Some("me@home.example".to_owned()),
+            password: Some("from-env".to_owned()),
+            ..Profile::default()
+        };
+        let work = profiles.resolve(flags, "work").unwrap();
+        assert_eq!(work.password.as_deref(), Some("from-env"));
+
+        // Giving the profile's own email as a flag still uses its password
+        let flags = Profile {
+            email: Some("me@work.example".to_owned()),
+            ..Profile::default()
+        };
+        let work = profiles.resolve(flags, "work").unwrap();
+        assert_eq!(work.password.as_deref(), Some("hunter2"));
+        assert!(profiles.resolve(Profile::default(), "missing").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::Endpoint;
+
+/// A request budget: at most `requests` requests every `per`. Up to `requests` requests
+/// can be sent in a burst after the limiter has been idle.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    pub fn new(requests: u32, per: Duration) -> RateLimit {
+        RateLimit { requests, per }
+    }
+
+    pub fn per_second(requests: u32) -> RateLimit {
+        RateLimit::new(requests, Duration::from_secs(1))
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    /// Tokens added per second
+    refill_rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> TokenBucket {
+        let capacity = f64::from(limit.requests.max(1));
+        TokenBucket {
+            capacity,
+            refill_rate: capacity / limit.per.as_secs_f64().max(f64::EPSILON),
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// How long until a token is available, zero if one is available now.
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((1.0 - self.tokens) / self.refill_rate)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    global: Option<TokenBucket>,
+    endpoints: HashMap<Endpoint, TokenBucket>,
+}
+
+/// Client-side token bucket rate limiter. Limits can be set for all requests combined and
+/// for individual endpoints; a request has to fit in both. Cloning the limiter shares the
+/// budget, so handing clones to several [`ResyClient`](crate::ResyClient)s keeps their
+/// combined request rate under the limits.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        RateLimiter::default()
+    }
+
+    /// Limits the combined rate of requests to every endpoint.
+    pub fn global(self, limit: RateLimit) -> RateLimiter {
+        self.buckets.lock().unwrap().global = Some(TokenBucket::new(limit));
+        self
+    }
+
+    /// Limits the rate of requests to a single endpoint.
+    pub fn endpoint(self, endpoint: Endpoint, limit: RateLimit) -> RateLimiter {
+        self.buckets
+            .lock()
+            .unwrap()
+            .endpoints
+            .insert(endpoint, TokenBucket::new(limit));
+        self
+    }
+
+    /// Waits until a request to the endpoint fits in the budget, then takes a token for it.
+    pub async fn acquire(&self, endpoint: Endpoint) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let now = Instant::now();
+                let Buckets { global, endpoints } = &mut *buckets;
+                let mut applicable: Vec<&mut TokenBucket> = global
+                    .iter_mut()
+                    .chain(endpoints.get_mut(&endpoint))
+                    .collect();
+                applicable.iter_mut().for_each(|b| b.refill(now));
+                let wait = applicable
+                    .iter()
+                    .map(|b| b.wait_time())
+                    .max()
+                    .unwrap_or_default();
+                if wait.is_zero() {
+                    // Only take tokens once every bucket has one so a request waiting on
+                    // one bucket doesn't drain the others.
+                    applicable.iter_mut().for_each(|b| b.tokens -= 1.0);
+                    return;
+                }
+                wait
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new()
+            .global(RateLimit::new(10, Duration::from_secs(1)))
+            .endpoint(
+                Endpoint::Find,
+                RateLimit::new(2, Duration::from_millis(200)),
+            );
+        let shared = limiter.clone();
+
+        let start = Instant::now();
+        // Burst of two is allowed right away, the third has to wait for a refill
+        limiter.acquire(Endpoint::Find).await;
+        shared.acquire(Endpoint::Find).await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        limiter.acquire(Endpoint::Find).await;
+        assert!(start.elapsed() >= Duration::from_millis(90));
+
+        // Other endpoints only count against the global budget
+        let start = Instant::now();
+        for _ in 0..5 {
+            shared.acquire(Endpoint::Search).await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
@@ -1,30 +1,33 @@
-use chrono::NaiveDateTime;
-use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, str::FromStr};
 
-#[derive(Debug, Deserialize, Clone)]
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::ResyError;
+
+/// A city in the Resy network, see [`crate::city`] for looking one up by name.
+#[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct RestaurantCityConfig {
-    country_code: String,
+    #[serde(default)]
+    pub name: String,
+    /// Short code Resy uses for the city, e.g. "ny"
+    #[serde(default)]
+    pub code: String,
+    /// e.g. "new-york-ny"
+    pub url_slug: String,
+    pub country_code: String,
     pub latitude: f32,
     pub longitude: f32,
-    url_slug: String,
-}
-
-impl RestaurantCityConfig {
-    pub fn is_match(&self, city: &str, country: &str) -> bool {
-        let country_match = self.country_code.eq_ignore_ascii_case(country);
-        let city_match = self
-            .url_slug
-            .to_ascii_lowercase()
-            .contains(&city.to_ascii_lowercase());
-
-        country_match && city_match
-    }
+    #[serde(default)]
+    pub time_zone: String,
 }
 
+/// Area to search in, a circle around a point.
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct GeoFilter {
     latitude: f32,
     longitude: f32,
+    /// In meters.
     radius: u16,
 }
 
@@ -36,6 +39,33 @@ impl GeoFilter {
             radius,
         }
     }
+
+    /// Area around the center of a city.
+    pub fn around(city_config: &RestaurantCityConfig, radius: u16) -> GeoFilter {
+        GeoFilter::new(city_config.latitude, city_config.longitude, radius)
+    }
+
+    /// Distance in meters from the center of the area to a point.
+    pub fn distance_to(&self, latitude: f64, longitude: f64) -> f64 {
+        const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+        let (lat1, lat2) = (f64::from(self.latitude).to_radians(), latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (longitude - f64::from(self.longitude)).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+    }
+
+    /// Whether a point is inside the area.
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        self.distance_to(latitude, longitude) <= f64::from(self.radius)
+    }
+}
+
+/// Limits search hits to venues with slots for the party on the day.
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct SlotFilter {
+    day: NaiveDate,
+    party_size: u8,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -43,6 +73,12 @@ pub struct RestaurantSearchRequest {
     availability: bool,
     geo: GeoFilter,
     query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slot_filter: Option<SlotFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    per_page: Option<u16>,
 }
 
 impl RestaurantSearchRequest {
@@ -51,8 +87,98 @@ impl RestaurantSearchRequest {
             availability,
             geo: geo.clone(),
             query: query.to_owned(),
+            slot_filter: None,
+            page: None,
+            per_page: None,
         }
     }
+
+    /// Asks for one page of hits, pages start at 1.
+    pub fn page(mut self, page: u32, per_page: u16) -> RestaurantSearchRequest {
+        self.page = Some(page);
+        self.per_page = Some(per_page);
+        self
+    }
+
+    /// Searches for every venue in the area with open slots, see [`DiscoverQuery`].
+    pub fn discover(query: &DiscoverQuery) -> RestaurantSearchRequest {
+        RestaurantSearchRequest {
+            availability: true,
+            geo: query.geo.clone(),
+            query: String::new(),
+            slot_filter: Some(SlotFilter {
+                day: query.date,
+                party_size: query.party_size,
+            }),
+            page: None,
+            per_page: Some(query.limit),
+        }
+    }
+}
+
+/// Number of venues a discover search returns unless told otherwise.
+pub const DEFAULT_DISCOVER_LIMIT: u16 = 20;
+
+/// Looks for open tables across an area instead of at one restaurant, e.g. "what's open
+/// for 4 in Brooklyn on Friday around 8pm".
+#[derive(Debug, Clone)]
+pub struct DiscoverQuery {
+    pub geo: GeoFilter,
+    pub date: NaiveDate,
+    pub party_size: u8,
+    /// Earliest start time of the slots to return, slots at any time are returned if
+    /// there's no window.
+    pub earliest: Option<NaiveTime>,
+    /// Latest start time of the slots to return.
+    pub latest: Option<NaiveTime>,
+    /// Max number of venues to return.
+    pub limit: u16,
+}
+
+impl DiscoverQuery {
+    pub fn new(geo: GeoFilter, date: NaiveDate, party_size: u8) -> DiscoverQuery {
+        DiscoverQuery {
+            geo,
+            date,
+            party_size,
+            earliest: None,
+            latest: None,
+            limit: DEFAULT_DISCOVER_LIMIT,
+        }
+    }
+
+    /// Only return slots starting from `earliest` to `latest`, both included. The search
+    /// covers a single day, so a window ending before it starts is cut off at the end of
+    /// the day rather than running into that morning.
+    pub fn time_window(mut self, earliest: NaiveTime, latest: NaiveTime) -> DiscoverQuery {
+        self.earliest = Some(earliest);
+        self.latest = Some(if latest < earliest {
+            NaiveTime::from_hms_nano_opt(23, 59, 59, 999_999_999).unwrap()
+        } else {
+            latest
+        });
+        self
+    }
+
+    pub fn limit(mut self, limit: u16) -> DiscoverQuery {
+        self.limit = limit;
+        self
+    }
+
+    /// Whether the slot is in the time window and takes the party.
+    pub fn matches(&self, slot: &ReservationSlot) -> bool {
+        let start = slot.date.start.time();
+        let in_window = self.earliest.is_none_or(|earliest| start >= earliest)
+            && self.latest.is_none_or(|latest| start <= latest);
+        in_window && slot.fits_party(self.party_size)
+    }
+}
+
+/// A venue found by a discover search along with its slots matching the search.
+#[derive(Debug, Clone)]
+pub struct DiscoveredVenue {
+    pub restaurant: RestaurantSearchResult,
+    pub slots: Vec<ReservationSlot>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -60,17 +186,172 @@ pub struct RestaurantSearchResult {
     #[serde(rename = "objectID")]
     pub object_id: String,
     pub name: String,
+    pub neighborhood: Option<String>,
+    #[serde(default)]
+    pub cuisine: Vec<String>,
+    /// From 1 to 4, how many dollar signs Resy shows.
+    pub price_range_id: Option<u8>,
+    pub rating: Option<SearchHitRating>,
+    #[serde(rename = "_geoloc")]
+    pub geoloc: Option<SearchHitGeo>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SearchHitRating {
+    pub average: f32,
+    #[serde(default)]
+    pub count: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SearchHitGeo {
+    pub lat: f64,
+    pub lng: f64,
+}
+
+impl RestaurantSearchResult {
+    /// Name along with the details that tell restaurants with the same name apart.
+    pub fn describe(&self) -> String {
+        let details: Vec<&str> = self
+            .neighborhood
+            .iter()
+            .chain(self.cuisine.iter())
+            .map(String::as_str)
+            .filter(|detail| !detail.is_empty())
+            .collect();
+        if details.is_empty() {
+            return format!("{} (Resy ID = {})", self.name, self.object_id);
+        }
+        format!(
+            "{} ({}, Resy ID = {})",
+            self.name,
+            details.join(", "),
+            self.object_id
+        )
+    }
+}
+
+/// A search hit along with how closely its name matches the name that was searched for.
+#[derive(Debug, Clone)]
+pub struct RankedRestaurant {
+    pub restaurant: RestaurantSearchResult,
+    /// From 0.0 to 1.0 when the names are the same, ignoring case and punctuation.
+    pub similarity: f32,
+}
+
+impl RankedRestaurant {
+    pub fn is_exact(&self) -> bool {
+        self.similarity >= 1.0
+    }
+}
+
+/// Format Resy uses for date times inside of slots.
+static RESY_DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Parses Resy's slot date times while deserializing so bad values surface as an error
+/// from the client call instead of a panic later on.
+mod resy_datetime {
+    use chrono::NaiveDateTime;
+    use serde::{de::Error, Deserialize, Deserializer};
+
+    use super::RESY_DATETIME_FORMAT;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        NaiveDateTime::parse_from_str(&value, RESY_DATETIME_FORMAT).map_err(D::Error::custom)
+    }
+}
+
+/// Parses optional date times in Resy's format, null is treated as missing.
+fn optional_resy_datetime<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| {
+            NaiveDateTime::parse_from_str(&value, RESY_DATETIME_FORMAT)
+                .map_err(serde::de::Error::custom)
+        })
+        .transpose()
+}
+
+/// Parses durations Resy sends as a number of seconds.
+fn optional_seconds<'de, D>(deserializer: D) -> Result<Option<TimeDelta>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<i64>::deserialize(deserializer)?.map(TimeDelta::seconds))
+}
+
+/// An amount of money, kept in cents to avoid float rounding. Resy sends amounts as decimal
+/// numbers in the venue's currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money {
+    cents: i64,
+}
+
+impl Money {
+    pub fn from_cents(cents: i64) -> Money {
+        Money { cents }
+    }
+
+    pub fn cents(&self) -> i64 {
+        self.cents
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.cents == 0
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.cents < 0 { "-" } else { "" };
+        let cents = self.cents.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+impl FromStr for Money {
+    type Err = ResyError;
+
+    /// Parses amounts like "25", "25.5" or "$25.50".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ResyError::InvalidConfig(format!("invalid amount of money {s}"));
+        let amount = s.trim().trim_start_matches('$');
+        let (units, cents) = amount.split_once('.').unwrap_or((amount, ""));
+        if units.is_empty() && cents.is_empty() || cents.len() > 2 {
+            return Err(invalid());
+        }
+        let parse = |digits: &str| -> Result<i64, ResyError> {
+            if digits.is_empty() {
+                return Ok(0);
+            }
+            if !digits.chars().all(|c| c.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            digits.parse().map_err(|_| invalid())
+        };
+        let cents = parse(cents)? * if cents.len() == 1 { 10 } else { 1 };
+        Ok(Money::from_cents(parse(units)? * 100 + cents))
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ReservationSlotDate {
-    pub start: String,
+    #[serde(with = "resy_datetime")]
+    pub start: NaiveDateTime,
+    /// When the table has to be given back.
+    #[serde(default, deserialize_with = "optional_resy_datetime")]
+    pub end: Option<NaiveDateTime>,
 }
 
 impl ReservationSlotDate {
     pub fn to_datetime(&self) -> NaiveDateTime {
-        NaiveDateTime::parse_from_str(&self.start, "%Y-%m-%d %H:%M:%S")
-            .expect("Invalid time received from Resy")
+        self.start
     }
 }
 
@@ -87,10 +368,506 @@ pub struct ReservationSlotConfig {
     pub token: String,
 }
 
+impl ReservationSlotConfig {
+    /// Parses the slot's token, see [`RgsToken`].
+    pub fn rgs_token(&self) -> Result<RgsToken, ResyError> {
+        self.token.parse()
+    }
+}
+
+/// Scheme and host every slot token starts with.
+static RGS_TOKEN_PREFIX: &str = "rgs://resy/";
+
+/// Format of the time in slot tokens.
+static RGS_TIME_FORMAT: &str = "%H:%M:%S";
+
+/// A slot token, which describes the slot well enough to get its details without
+/// looking it up again. Tokens look like
+/// `rgs://resy/<venue>/<template>/<service type>/<start day>/<end day>/<time>/<party size>/<table type>`,
+/// e.g. `rgs://resy/65615/1844312/3/2024-11-07/2024-11-07/12:00:00/2/Inside`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RgsToken {
+    pub venue_id: u64,
+    pub template_id: u64,
+    pub service_type_id: u32,
+    pub start_date: NaiveDate,
+    /// Same as the start date unless the slot runs past midnight.
+    pub end_date: NaiveDate,
+    pub time: NaiveTime,
+    pub party_size: u8,
+    /// Can contain slashes, e.g. "Bar/Counter".
+    pub table_type: String,
+}
+
+impl RgsToken {
+    /// Venue a token is for, read without parsing the rest of it so that tokens in a
+    /// format this version doesn't know still tell where they're from.
+    pub fn venue_id_of(token: &str) -> Option<u64> {
+        token
+            .strip_prefix(RGS_TOKEN_PREFIX)?
+            .split('/')
+            .next()?
+            .parse()
+            .ok()
+    }
+
+    pub fn start(&self) -> NaiveDateTime {
+        self.start_date.and_time(self.time)
+    }
+
+    /// Token of the same slot on another day. Tokens are built from the venue's templates
+    /// so this is what Resy will hand out for the day once it's released.
+    pub fn on_date(&self, date: NaiveDate) -> RgsToken {
+        RgsToken {
+            start_date: date,
+            end_date: date + (self.end_date - self.start_date),
+            ..self.clone()
+        }
+    }
+
+    /// Request for the slot's details, which doesn't need the slot to be found first.
+    pub fn details_request(&self) -> ReservationDetailsRequest {
+        ReservationDetailsRequest::new(
+            self.to_string(),
+            self.start_date.format("%Y-%m-%d").to_string(),
+            self.party_size.to_string(),
+        )
+    }
+}
+
+impl FromStr for RgsToken {
+    type Err = ResyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| ResyError::InvalidToken {
+            token: s.to_owned(),
+            reason: reason.to_owned(),
+        };
+        let path = s
+            .strip_prefix(RGS_TOKEN_PREFIX)
+            .ok_or_else(|| invalid("it doesn't start with rgs://resy/"))?;
+        let parts: Vec<&str> = path.splitn(8, '/').collect();
+        let [venue_id, template_id, service_type_id, start_date, end_date, time, party_size, table_type] =
+            parts[..]
+        else {
+            return Err(invalid("it doesn't have 8 parts"));
+        };
+        let date = |value: &str, name: &str| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| invalid(&format!("{name} {value} isn't a date")))
+        };
+        let number = |value: &str, name: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| invalid(&format!("{name} {value} isn't a number")))
+        };
+        let token = RgsToken {
+            venue_id: number(venue_id, "venue")?,
+            template_id: number(template_id, "template")?,
+            service_type_id: service_type_id
+                .parse()
+                .map_err(|_| invalid(&format!("service type {service_type_id} isn't a number")))?,
+            start_date: date(start_date, "start day")?,
+            end_date: date(end_date, "end day")?,
+            time: NaiveTime::parse_from_str(time, RGS_TIME_FORMAT)
+                .map_err(|_| invalid(&format!("time {time} isn't a time")))?,
+            party_size: party_size
+                .parse()
+                .map_err(|_| invalid(&format!("party size {party_size} isn't a number")))?,
+            table_type: table_type.to_owned(),
+        };
+        if token.end_date < token.start_date {
+            return Err(invalid("it ends before it starts"));
+        }
+        if token.party_size == 0 {
+            return Err(invalid("the party size is 0"));
+        }
+        if token.table_type.is_empty() {
+            return Err(invalid("the table type is missing"));
+        }
+        Ok(token)
+    }
+}
+
+impl fmt::Display for RgsToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}/{}/{}/{}/{}/{}/{}/{}",
+            RGS_TOKEN_PREFIX,
+            self.venue_id,
+            self.template_id,
+            self.service_type_id,
+            self.start_date.format("%Y-%m-%d"),
+            self.end_date.format("%Y-%m-%d"),
+            self.time.format(RGS_TIME_FORMAT),
+            self.party_size,
+            self.table_type
+        )
+    }
+}
+
+impl Serialize for RgsToken {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RgsToken {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Party sizes the slot can be booked for.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReservationSlotSize {
+    pub min: u8,
+    pub max: u8,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ServiceType {
+    pub id: u8,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShiftService {
+    #[serde(rename = "type")]
+    pub service_type: ServiceType,
+}
+
+/// The shift (lunch, dinner, etc.) the slot is part of.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReservationSlotShift {
+    pub id: u64,
+    pub service: ShiftService,
+    pub day: NaiveDate,
+}
+
+/// Resy's ranking of the slot, higher is better.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReservationSlotScore {
+    pub total: f32,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MarketDates {
+    /// When the slot went on sale.
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub on: Option<DateTime<Utc>>,
+    /// When the slot stops being sold.
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    pub off: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReservationSlotMarket {
+    #[serde(default)]
+    pub date: MarketDates,
+}
+
+/// How long the table is held for.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TurnTime {
+    #[serde(default, deserialize_with = "optional_seconds")]
+    pub actual: Option<TimeDelta>,
+    #[serde(default, deserialize_with = "optional_seconds")]
+    pub estimated: Option<TimeDelta>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReservationSlotTime {
+    #[serde(default)]
+    pub turn: TurnTime,
+}
+
+/// Payment terms of a slot. Fees are per guest.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SlotPayment {
+    #[serde(default)]
+    pub is_paid: bool,
+    /// Charged when booking and put towards the bill.
+    #[serde(default, deserialize_with = "lenient_money")]
+    pub deposit_fee: Option<Money>,
+    /// Charged when cancelling after the cancellation cut-off.
+    #[serde(default, deserialize_with = "lenient_money")]
+    pub cancellation_fee: Option<Money>,
+    /// How long before the reservation it can be cancelled for free.
+    #[serde(
+        default,
+        rename = "secs_cancel_cut_off",
+        deserialize_with = "optional_seconds"
+    )]
+    pub cancel_cut_off: Option<TimeDelta>,
+    /// Last time the reservation can be cancelled for free.
+    #[serde(
+        default,
+        rename = "time_cancel_cut_off",
+        deserialize_with = "optional_resy_datetime"
+    )]
+    pub cancel_cut_off_time: Option<NaiveDateTime>,
+    /// How long before the reservation it can be changed.
+    #[serde(
+        default,
+        rename = "secs_change_cut_off",
+        deserialize_with = "optional_seconds"
+    )]
+    pub change_cut_off: Option<TimeDelta>,
+    /// Last time the reservation can be changed.
+    #[serde(
+        default,
+        rename = "time_change_cut_off",
+        deserialize_with = "optional_resy_datetime"
+    )]
+    pub change_cut_off_time: Option<NaiveDateTime>,
+}
+
+impl SlotPayment {
+    /// Deposit for the whole party.
+    pub fn deposit(&self, party_size: u8) -> Money {
+        Money::from_cents(self.deposit_fee.unwrap_or_default().cents() * i64::from(party_size))
+    }
+
+    /// Cancellation fee for the whole party.
+    pub fn cancellation_fee(&self, party_size: u8) -> Money {
+        Money::from_cents(self.cancellation_fee.unwrap_or_default().cents() * i64::from(party_size))
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct ReservationSlot {
     pub date: ReservationSlotDate,
     pub config: ReservationSlotConfig,
+    pub size: Option<ReservationSlotSize>,
+    /// Number of tables left for the slot.
+    #[serde(default)]
+    pub quantity: u32,
+    pub shift: Option<ReservationSlotShift>,
+    pub score: Option<ReservationSlotScore>,
+    pub market: Option<ReservationSlotMarket>,
+    pub time: Option<ReservationSlotTime>,
+    #[serde(default)]
+    pub payment: SlotPayment,
+}
+
+impl ReservationSlot {
+    /// Service type (lunch, dinner, etc.) of the shift the slot is in.
+    pub fn service_type_id(&self) -> Option<u8> {
+        self.shift.as_ref().map(|s| s.service.service_type.id)
+    }
+
+    /// How long the table is held for, falling back to Resy's estimate and then to the
+    /// slot's start and end. `None` if Resy sent none of them.
+    pub fn turn_time(&self) -> Option<TimeDelta> {
+        let turn = self.time.as_ref().map(|t| &t.turn);
+        turn.and_then(|t| t.actual.or(t.estimated))
+            .or_else(|| self.date.end.map(|end| end - self.date.start))
+    }
+
+    pub fn score(&self) -> Option<f32> {
+        self.score.as_ref().map(|s| s.total)
+    }
+
+    /// Whether the slot takes a party of the given size. Slots without size bounds are
+    /// assumed to take any party.
+    pub fn fits_party(&self, party_size: u8) -> bool {
+        self.size
+            .as_ref()
+            .is_none_or(|size| (size.min..=size.max).contains(&party_size))
+    }
+
+    /// Whether booking the slot charges a deposit or risks a cancellation fee.
+    pub fn is_paid(&self) -> bool {
+        self.payment.is_paid
+            || self.payment.deposit_fee.is_some_and(|fee| !fee.is_zero())
+            || self
+                .payment
+                .cancellation_fee
+                .is_some_and(|fee| !fee.is_zero())
+    }
+
+    /// Whether Resy is selling the slot at the given time.
+    pub fn is_on_market(&self, now: DateTime<Utc>) -> bool {
+        self.market.as_ref().is_none_or(|m| {
+            m.date.on.is_none_or(|on| on <= now) && m.date.off.is_none_or(|off| now < off)
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct VenueId {
+    pub resy: u32,
+}
+
+/// Restaurant group the venue belongs to, e.g. all restaurants run by the same owner.
+#[derive(Debug, Deserialize, Clone)]
+pub struct VenueGroup {
+    pub id: u32,
+    pub name: String,
+    /// IDs of every venue in the group.
+    #[serde(default)]
+    pub venues: Vec<u32>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct VenueGeo {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct VenueLocation {
+    pub time_zone: String,
+    pub neighborhood: Option<String>,
+    pub geo: Option<VenueGeo>,
+    /// Code of the city the venue is in, matches [`RestaurantCityConfig::code`]
+    pub code: Option<String>,
+    /// Name of the city the venue is in
+    pub name: Option<String>,
+    /// Slug of the city the venue is in, matches [`RestaurantCityConfig::url_slug`]
+    pub url_slug: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct VenueWaitlist {
+    /// Resy sends 0 or 1
+    pub available: u8,
+    pub label: Option<String>,
+}
+
+impl VenueWaitlist {
+    pub fn is_available(&self) -> bool {
+        self.available != 0
+    }
+}
+
+/// Window in which a notification can be requested for a service type.
+#[derive(Debug, Deserialize, Clone)]
+pub struct VenueNotifyOption {
+    pub service_type_id: u8,
+    #[serde(with = "resy_datetime")]
+    pub min_request_datetime: NaiveDateTime,
+    #[serde(with = "resy_datetime")]
+    pub max_request_datetime: NaiveDateTime,
+    pub step_minutes: u32,
+}
+
+/// A restaurant on Resy, as returned by the find and venue endpoints.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Venue {
+    pub id: VenueId,
+    pub name: String,
+    /// Cuisine, e.g. "Italian"
+    #[serde(rename = "type")]
+    pub cuisine: String,
+    pub url_slug: Option<String>,
+    /// From 1 to 4, the number of dollar signs shown in the app
+    pub price_range: u8,
+    pub rating: Option<f32>,
+    pub total_ratings: Option<u32>,
+    pub location: VenueLocation,
+    /// Service types (lunch, dinner, etc.) the venue takes reservations for, keyed by ID.
+    /// The values don't seem to hold anything useful yet.
+    #[serde(default)]
+    pub service_types: HashMap<String, serde_json::Value>,
+    pub venue_group: Option<VenueGroup>,
+    pub waitlist: Option<VenueWaitlist>,
+    #[serde(default)]
+    pub notify_options: Vec<VenueNotifyOption>,
+    /// Whether Resy asks for a captcha when booking at this venue.
+    #[serde(default)]
+    pub feature_recaptcha: bool,
+}
+
+impl Venue {
+    /// IDs of the service types the venue takes reservations for, sorted.
+    pub fn service_type_ids(&self) -> Vec<u8> {
+        let mut ids: Vec<u8> = self
+            .service_types
+            .keys()
+            .filter_map(|id| id.parse().ok())
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+}
+
+/// A venue along with the slots it has open on the day that was requested.
+#[derive(Debug, Deserialize, Clone)]
+pub struct VenueAvailability {
+    pub venue: Venue,
+    #[serde(default)]
+    pub slots: Vec<ReservationSlot>,
+}
+
+/// Whether a venue takes reservations on a day.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DayStatus {
+    /// At least one slot is open.
+    Available,
+    /// The venue is open but every slot has been booked.
+    SoldOut,
+    /// The venue isn't taking reservations, Resy also uses "not available" for this.
+    #[serde(alias = "not available")]
+    Closed,
+    /// A status Resy added that isn't known here yet, or a day the calendar doesn't cover.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CalendarInventory {
+    pub reservation: DayStatus,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    pub inventory: CalendarInventory,
+}
+
+impl CalendarDay {
+    pub fn status(&self) -> DayStatus {
+        self.inventory.reservation
+    }
+}
+
+/// Reservation status of a venue for a range of days.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AvailabilityCalendar {
+    /// Furthest day out the venue has released reservations for.
+    pub last_calendar_day: Option<NaiveDate>,
+    #[serde(rename = "scheduled", default)]
+    pub days: Vec<CalendarDay>,
+}
+
+impl AvailabilityCalendar {
+    /// Status on the given day. Resy's calendar stops at the venue's booking horizon, so
+    /// nothing is known about days it leaves out.
+    pub fn status_on(&self, date: NaiveDate) -> DayStatus {
+        self.days
+            .iter()
+            .find(|day| day.date == date)
+            .map_or(DayStatus::Unknown, CalendarDay::status)
+    }
+
+    /// Days that still have a slot open.
+    pub fn available_days(&self) -> impl Iterator<Item = NaiveDate> + '_ {
+        self.days
+            .iter()
+            .filter(|day| day.status() == DayStatus::Available)
+            .map(|day| day.date)
+    }
 }
 
 /// Request params to get details on a reservation. The response will include the
@@ -115,10 +892,121 @@ impl ReservationDetailsRequest {
 #[derive(Debug, Deserialize, Clone)]
 pub struct PaymentMethod {
     pub id: u32,
+    /// Card network, e.g. "visa"
+    #[serde(rename = "type")]
+    pub brand: Option<String>,
+    /// Last four digits of the card number.
+    #[serde(rename = "display")]
+    pub last4: Option<String>,
+    pub exp_month: Option<u8>,
+    pub exp_year: Option<u16>,
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+impl PaymentMethod {
+    /// e.g. "visa ending in 4242, expires 12/2030 (default)"
+    pub fn describe(&self) -> String {
+        let mut description = format!(
+            "{} ending in {}",
+            self.brand.as_deref().unwrap_or("card"),
+            self.last4.as_deref().unwrap_or("????")
+        );
+        if let (Some(month), Some(year)) = (self.exp_month, self.exp_year) {
+            description.push_str(&format!(", expires {:02}/{}", month, year));
+        }
+        if self.is_default {
+            description.push_str(" (default)");
+        }
+        description
+    }
+}
+
+/// Picks which of the account's cards to pay with.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum PaymentSelector {
+    /// The card marked as default on the account.
+    #[default]
+    Default,
+    Id(u32),
+    Last4(String),
+}
+
+impl FromStr for PaymentSelector {
+    type Err = ResyError;
+
+    /// Parses "default", "id:<id>", "last4:<digits>" or a bare number. Bare four digit
+    /// numbers are taken as the last four digits of a card, other numbers as an id.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            ResyError::InvalidConfig(format!(
+                "invalid payment method {s}, expected default, an id or the last 4 digits of a card"
+            ))
+        };
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("default") {
+            return Ok(PaymentSelector::Default);
+        }
+        if let Some(id) = s.strip_prefix("id:") {
+            return id.parse().map(PaymentSelector::Id).map_err(|_| invalid());
+        }
+        let last4 = s.strip_prefix("last4:").unwrap_or(s);
+        if last4.len() == 4 && last4.chars().all(|c| c.is_ascii_digit()) {
+            return Ok(PaymentSelector::Last4(last4.to_owned()));
+        }
+        s.parse().map(PaymentSelector::Id).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for PaymentSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaymentSelector::Default => write!(f, "the default card"),
+            PaymentSelector::Id(id) => write!(f, "id {}", id),
+            PaymentSelector::Last4(last4) => write!(f, "a card ending in {}", last4),
+        }
+    }
+}
+
+impl PaymentSelector {
+    /// Picks the matching card. When no card is marked as default the first one is used.
+    pub fn select<'a>(
+        &self,
+        payment_methods: &'a [PaymentMethod],
+    ) -> Result<&'a PaymentMethod, ResyError> {
+        if payment_methods.is_empty() {
+            return Err(ResyError::NoPaymentMethod);
+        }
+        let selected = match self {
+            PaymentSelector::Default => payment_methods
+                .iter()
+                .find(|p| p.is_default)
+                .or(payment_methods.first()),
+            PaymentSelector::Id(id) => payment_methods.iter().find(|p| p.id == *id),
+            PaymentSelector::Last4(last4) => payment_methods
+                .iter()
+                .find(|p| p.last4.as_deref() == Some(last4.as_str())),
+        };
+        selected.ok_or_else(|| ResyError::PaymentMethodNotFound {
+            selector: self.to_string(),
+            available: payment_methods
+                .iter()
+                .map(PaymentMethod::describe)
+                .collect(),
+        })
+    }
+}
+
+/// The account details returned by /2/user, only the parts we use.
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct UserAccount {
+    #[serde(default)]
+    pub payment_methods: Vec<PaymentMethod>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 struct DetailsUser {
+    #[serde(default)]
     payment_methods: Vec<PaymentMethod>,
 }
 
@@ -127,20 +1015,209 @@ pub struct BookToken {
     pub value: String,
 }
 
+/// The slot being booked. Resy's booking response only contains identifiers so this is
+/// used to fill out the [`BookingConfirmation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookingSpecs {
+    pub venue_id: String,
+    pub date: NaiveDate,
+    pub time: NaiveTime,
+    pub party_size: u8,
+}
+
+impl BookingSpecs {
+    /// Booking a found slot for the party. Only the venue is needed from the slot's token,
+    /// which Resy may have changed the rest of.
+    pub fn for_slot(slot: &ReservationSlot, party_size: u8) -> Result<BookingSpecs, ResyError> {
+        let venue_id = match slot.config.rgs_token() {
+            Ok(token) => token.venue_id,
+            Err(e) => RgsToken::venue_id_of(&slot.config.token).ok_or(e)?,
+        };
+        let start = slot.date.to_datetime();
+        Ok(BookingSpecs {
+            venue_id: venue_id.to_string(),
+            date: start.date(),
+            time: start.time(),
+            party_size,
+        })
+    }
+}
+
+impl From<&RgsToken> for BookingSpecs {
+    fn from(token: &RgsToken) -> Self {
+        BookingSpecs {
+            venue_id: token.venue_id.to_string(),
+            date: token.start_date,
+            time: token.time,
+            party_size: token.party_size,
+        }
+    }
+}
+
+/// Amounts that may be sent as a bare number, as a string or as an object with an amount.
+/// Missing amounts are treated as no charge, but an amount that can't be read is an error
+/// rather than being mistaken for no charge.
+fn lenient_money<'de, D>(deserializer: D) -> Result<Option<Money>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    fn amount(value: &serde_json::Value) -> Result<Option<Money>, String> {
+        match value {
+            serde_json::Value::Null => Ok(None),
+            serde_json::Value::Number(number) => match number.as_f64() {
+                Some(amount) => Ok(Some(Money::from_cents((amount * 100.0).round() as i64))),
+                None => Err(format!("invalid amount of money {number}")),
+            },
+            serde_json::Value::String(amount) => amount
+                .parse()
+                .map(Some)
+                .map_err(|e: ResyError| e.to_string()),
+            serde_json::Value::Object(object) => match object.get("amount") {
+                Some(value) => amount(value),
+                None => Err(format!("no amount in {value}")),
+            },
+            other => Err(format!("invalid amount of money {other}")),
+        }
+    }
+    amount(&serde_json::Value::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// Charges for the whole party, as quoted when the slot's details are fetched.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PaymentAmounts {
+    #[serde(default, deserialize_with = "lenient_money")]
+    pub deposit_fee: Option<Money>,
+    #[serde(default, deserialize_with = "lenient_money")]
+    pub cancellation_fee: Option<Money>,
+    #[serde(default, deserialize_with = "lenient_money")]
+    pub service_charge: Option<Money>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DetailsPayment {
+    #[serde(default)]
+    pub amounts: PaymentAmounts,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DetailsCancellation {
+    /// Charged for the whole party when cancelling after the cut-off.
+    #[serde(default, deserialize_with = "lenient_money")]
+    pub fee: Option<Money>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct ReservationDetails {
     user: DetailsUser,
     pub book_token: BookToken,
+    #[serde(default)]
+    pub payment: DetailsPayment,
+    #[serde(default)]
+    pub cancellation: DetailsCancellation,
 }
 
 impl ReservationDetails {
-    /// Tries to get the first payment ID a user has. A user is not guaranteed to
-    /// have any payment methods on file.
+    /// Deposit charged for the whole party when booking.
+    pub fn deposit(&self) -> Money {
+        self.payment.amounts.deposit_fee.unwrap_or_default()
+    }
+
+    /// Fee charged for the whole party when cancelling late. Resy sends it in either
+    /// the payment amounts or the cancellation terms.
+    pub fn cancellation_fee(&self) -> Money {
+        self.payment
+            .amounts
+            .cancellation_fee
+            .max(self.cancellation.fee)
+            .unwrap_or_default()
+    }
+
+    /// Service charge added to the bill, it isn't charged when booking.
+    pub fn service_charge(&self) -> Money {
+        self.payment.amounts.service_charge.unwrap_or_default()
+    }
+
+    /// Cards the user has on file, there may not be any.
+    pub fn payment_methods(&self) -> &[PaymentMethod] {
+        &self.user.payment_methods
+    }
+
+    /// Picks the card to book with, see [`PaymentSelector::select`].
+    pub fn select_payment_method(
+        &self,
+        selector: &PaymentSelector,
+    ) -> Result<PaymentMethod, ResyError> {
+        selector.select(&self.user.payment_methods).cloned()
+    }
+
+    /// Gets the user's default card. A user is not guaranteed to have any payment methods
+    /// on file.
     pub fn get_payment_id(&self) -> Option<PaymentMethod> {
-        self.user.payment_methods.first().cloned()
+        self.select_payment_method(&PaymentSelector::Default).ok()
     }
 }
 
+/// Response from the booking endpoint.
+#[derive(Debug, Deserialize)]
+pub(crate) struct BookResponse {
+    pub resy_token: String,
+    pub reservation_id: u64,
+}
+
+/// Details about a successfully booked reservation. `reservation_id` and `resy_token`
+/// identify the booking with Resy and are needed to look it up or cancel it later.
+#[derive(Debug, Clone, Serialize)]
+pub struct BookingConfirmation {
+    pub reservation_id: u64,
+    pub resy_token: String,
+    pub venue_id: String,
+    pub date: NaiveDate,
+    pub time: NaiveTime,
+    pub party_size: u8,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UserReservationVenue {
+    pub id: u32,
+    /// Not part of the reservation itself, Resy sends venue details separately in the
+    /// listing response so this is filled in by the client.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UserReservationConfig {
+    /// Type of table booked ("Indoors", "Bar", etc.)
+    #[serde(rename = "type")]
+    pub slot_type: String,
+}
+
+/// A reservation the user has booked, either upcoming or in the past.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UserReservation {
+    pub reservation_id: u64,
+    /// Token identifying the booking, needed to cancel the reservation.
+    pub resy_token: String,
+    pub day: NaiveDate,
+    pub time_slot: NaiveTime,
+    pub num_seats: u8,
+    pub venue: UserReservationVenue,
+    pub config: Option<UserReservationConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct UserReservationVenueDetails {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UserReservationsResponse {
+    pub reservations: Vec<UserReservation>,
+    /// Venue details keyed by the venue ID
+    #[serde(default)]
+    pub venues: HashMap<String, UserReservationVenueDetails>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ResyNotificationSpec {
     pub venue_id: u32,
@@ -162,3 +1239,275 @@ pub struct ResyNotification {
 pub struct ResyNotificationResults {
     pub notify: Vec<ResyNotification>,
 }
+
+/// Response from logging in with an email and password. Resy sends back the whole user
+/// profile but only the token is needed.
+#[derive(Debug, Deserialize)]
+pub(crate) struct AuthResponse {
+    pub token: String,
+}
+
+/// Error payload Resy sends back alongside non-success statuses. Every field is optional
+/// since not all errors include a body.
+#[derive(Debug, Deserialize, Default)]
+pub struct ResyErrorResponse {
+    pub message: Option<String>,
+    pub status: Option<u16>,
+    pub code: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_venue_from_find() {
+        let find: serde_json::Value =
+            serde_json::from_str(include_str!("test_data/test_find.json")).unwrap();
+        let availability: VenueAvailability =
+            serde_json::from_value(find["results"]["venues"][0].clone()).unwrap();
+        let venue = availability.venue;
+        assert_eq!(venue.id.resy, 65615);
+        assert_eq!(venue.cuisine, "Italian");
+        assert_eq!(
+            venue.location.neighborhood.as_deref(),
+            Some("Headhouse Square")
+        );
+        assert_eq!(venue.service_type_ids(), vec![2, 3]);
+        assert_eq!(venue.venue_group.unwrap().name, "Starr Restaurants");
+        assert!(!venue.waitlist.unwrap().is_available());
+        let slot = &availability.slots[0];
+        assert_eq!(
+            slot.date.end.unwrap() - slot.date.start,
+            TimeDelta::minutes(75)
+        );
+        assert_eq!(slot.turn_time(), Some(TimeDelta::minutes(75)));
+        assert_eq!(slot.quantity, 4);
+        assert_eq!(slot.service_type_id(), Some(3));
+        assert_eq!(slot.score(), Some(82.72));
+        assert!(slot.fits_party(4) && !slot.fits_party(5));
+        assert!(!slot.is_paid());
+        assert!(slot.is_on_market(DateTime::from_timestamp(1730000000, 0).unwrap()));
+        assert_eq!(slot.config.rgs_token().unwrap().start(), slot.date.start);
+
+        // Slots missing the details that aren't needed to book them still parse
+        let slot: ReservationSlot = serde_json::from_value(serde_json::json!({
+            "date": {"start": "2024-11-07 12:00:00", "end": null},
+            "config": {
+                "id": 1,
+                "type": "Inside",
+                "token": "rgs://resy/65615/1844312/3/2024-11-07/2024-11-07/12:00:00/2/Inside",
+            },
+            "market": {"date": {"on": 1730000000}},
+            "time": {"turn": {"estimated": 5400}},
+        }))
+        .unwrap();
+        assert_eq!(slot.turn_time(), Some(TimeDelta::minutes(90)));
+        assert!(slot.is_on_market(DateTime::from_timestamp(1730000000, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_discover_window() {
+        let find: serde_json::Value =
+            serde_json::from_str(include_str!("test_data/test_find.json")).unwrap();
+        let slot: ReservationSlot =
+            serde_json::from_value(find["results"]["venues"][0]["slots"][0].clone()).unwrap();
+        let at = |time: &str| {
+            let mut slot = slot.clone();
+            slot.date.start = slot
+                .date
+                .start
+                .date()
+                .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap());
+            slot
+        };
+        let time = |t| NaiveTime::parse_from_str(t, "%H:%M").unwrap();
+        let query = DiscoverQuery::new(GeoFilter::new(0.0, 0.0, 1000), slot.date.start.date(), 2);
+
+        let evening = query.clone().time_window(time("19:00"), time("20:00"));
+        assert!(evening.matches(&at("19:30")));
+        assert!(!evening.matches(&at("20:15")));
+
+        // A window crossing midnight ends with the day searched, that day's early slots
+        // come before the window rather than after it
+        let late = query.time_window(time("22:00"), time("01:00"));
+        assert!(late.matches(&at("22:00")));
+        assert!(late.matches(&at("23:45")));
+        assert!(!late.matches(&at("00:30")));
+        assert!(!late.matches(&at("21:45")));
+    }
+
+    #[test]
+    fn test_availability_calendar() {
+        let calendar: AvailabilityCalendar = serde_json::from_value(serde_json::json!({
+            "last_calendar_day": "2024-11-30",
+            "scheduled": [
+                {"date": "2024-11-01", "inventory": {"reservation": "available", "event": "not available"}},
+                {"date": "2024-11-02", "inventory": {"reservation": "sold-out"}},
+                {"date": "2024-11-03", "inventory": {"reservation": "not available"}},
+                {"date": "2024-11-05", "inventory": {"reservation": "waitlist"}},
+            ],
+        }))
+        .unwrap();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 11, d).unwrap();
+        assert_eq!(calendar.status_on(day(1)), DayStatus::Available);
+        assert_eq!(calendar.status_on(day(2)), DayStatus::SoldOut);
+        assert_eq!(calendar.status_on(day(3)), DayStatus::Closed);
+        assert_eq!(calendar.status_on(day(4)), DayStatus::Unknown);
+        assert_eq!(calendar.status_on(day(5)), DayStatus::Unknown);
+        // Asking past the last day of the calendar doesn't make those days closed
+        let past_horizon = NaiveDate::from_ymd_opt(2024, 12, 5).unwrap();
+        assert_eq!(calendar.status_on(past_horizon), DayStatus::Unknown);
+        assert_eq!(calendar.available_days().collect::<Vec<_>>(), vec![day(1)]);
+    }
+
+    #[test]
+    fn test_rgs_token() {
+        let raw = "rgs://resy/65615/1844312/3/2024-11-07/2024-11-07/12:00:00/2/Inside";
+        let token: RgsToken = raw.parse().unwrap();
+        assert_eq!(token.venue_id, 65615);
+        assert_eq!(token.template_id, 1844312);
+        assert_eq!(token.service_type_id, 3);
+        assert_eq!(token.start().to_string(), "2024-11-07 12:00:00");
+        assert_eq!(token.party_size, 2);
+        assert_eq!(token.table_type, "Inside");
+        assert_eq!(token.to_string(), raw);
+
+        let counter: RgsToken = "rgs://resy/1/2/3/2024-11-07/2024-11-08/23:30:00/4/Bar/Counter"
+            .parse()
+            .unwrap();
+        assert_eq!(counter.table_type, "Bar/Counter");
+        assert_eq!(
+            counter
+                .on_date(NaiveDate::from_ymd_opt(2024, 11, 30).unwrap())
+                .to_string(),
+            "rgs://resy/1/2/3/2024-11-30/2024-12-01/23:30:00/4/Bar/Counter"
+        );
+        for invalid in [
+            "https://resy.com/65615",
+            "rgs://resy/65615/1844312/3/2024-11-07",
+            "rgs://resy/x/1844312/3/2024-11-07/2024-11-07/12:00:00/2/Inside",
+            "rgs://resy/65615/1844312/3/2024-11-07/2024-11-06/12:00:00/2/Inside",
+            "rgs://resy/65615/1844312/3/2024-11-07/2024-11-07/12:00/2/Inside",
+            "rgs://resy/65615/1844312/3/2024-11-07/2024-11-07/12:00:00/0/Inside",
+        ] {
+            assert!(matches!(
+                invalid.parse::<RgsToken>(),
+                Err(ResyError::InvalidToken { .. })
+            ));
+        }
+        // The venue can still be read from a token in a format that isn't known
+        assert_eq!(
+            RgsToken::venue_id_of("rgs://resy/65615/1844312/3/2024-11-07"),
+            Some(65615)
+        );
+        assert_eq!(RgsToken::venue_id_of("https://resy.com/65615"), None);
+    }
+
+    #[test]
+    fn test_payment_selector() {
+        let card = |id, last4: &str, is_default| PaymentMethod {
+            id,
+            brand: Some("visa".to_owned()),
+            last4: Some(last4.to_owned()),
+            exp_month: Some(1),
+            exp_year: Some(2030),
+            is_default,
+        };
+        let cards = vec![card(11, "1881", false), card(12, "4242", true)];
+        let select = |selector: &str| {
+            selector
+                .parse::<PaymentSelector>()
+                .unwrap()
+                .select(&cards)
+                .map(|p| p.id)
+        };
+        assert_eq!(select("default").unwrap(), 12);
+        assert_eq!(select("1881").unwrap(), 11);
+        assert_eq!(select("id:11").unwrap(), 11);
+        assert_eq!(select("12").unwrap(), 12);
+        assert!(matches!(
+            select("0000"),
+            Err(ResyError::PaymentMethodNotFound { .. })
+        ));
+        assert!(matches!(
+            PaymentSelector::Default.select(&[]),
+            Err(ResyError::NoPaymentMethod)
+        ));
+        assert!("visa".parse::<PaymentSelector>().is_err());
+        assert_eq!(
+            cards[1].describe(),
+            "visa ending in 4242, expires 01/2030 (default)"
+        );
+    }
+
+    #[test]
+    fn test_slot_payment() {
+        let payment: SlotPayment = serde_json::from_value(serde_json::json!({
+            "is_paid": true,
+            "deposit_fee": 25.5,
+            "cancellation_fee": 50,
+            "secs_cancel_cut_off": 86400,
+            "time_cancel_cut_off": "2024-11-06 12:00:00",
+            "secs_change_cut_off": null,
+            "time_change_cut_off": null,
+        }))
+        .unwrap();
+        assert_eq!(payment.deposit(2), Money::from_cents(5100));
+        assert_eq!("$25.5".parse::<Money>().unwrap(), Money::from_cents(2550));
+        assert_eq!("100".parse::<Money>().unwrap().to_string(), "100.00");
+        assert!("-5".parse::<Money>().is_err());
+        assert_eq!(payment.cancellation_fee(2).to_string(), "100.00");
+        assert_eq!(payment.cancel_cut_off, Some(TimeDelta::days(1)));
+        assert!(payment.cancel_cut_off_time.is_some());
+        assert!(payment.change_cut_off.is_none());
+
+        // Slots read fees the same way the details do
+        let payment: SlotPayment = serde_json::from_value(serde_json::json!({
+            "is_paid": true,
+            "deposit_fee": "25.50",
+            "cancellation_fee": {"amount": 50},
+        }))
+        .unwrap();
+        assert_eq!(payment.deposit(2), Money::from_cents(5100));
+        assert_eq!(payment.cancellation_fee(2), Money::from_cents(10000));
+
+        let details: ReservationDetails = serde_json::from_value(serde_json::json!({
+            "user": {"payment_methods": []},
+            "book_token": {"value": "token"},
+            "payment": {"amounts": {
+                "deposit_fee": 200.0,
+                "service_charge": {"amount": 12.5, "display": "Service charge"},
+            }},
+            "cancellation": {"fee": {"amount": 50.0, "date_cut_off": null}},
+        }))
+        .unwrap();
+        assert_eq!(details.deposit(), Money::from_cents(20000));
+        assert_eq!(details.cancellation_fee(), Money::from_cents(5000));
+        assert_eq!(details.service_charge(), Money::from_cents(1250));
+
+        let details: ReservationDetails = serde_json::from_value(serde_json::json!({
+            "user": {"payment_methods": []},
+            "book_token": {"value": "token"},
+            "payment": {"amounts": {"deposit_fee": "100.00"}},
+        }))
+        .unwrap();
+        assert_eq!(details.deposit(), Money::from_cents(10000));
+
+        // Fees that can't be read aren't taken to be free
+        for deposit_fee in [
+            serde_json::json!("one hundred"),
+            serde_json::json!({"display": "$100"}),
+            serde_json::json!([100]),
+        ] {
+            assert!(
+                serde_json::from_value::<ReservationDetails>(serde_json::json!({
+                    "user": {"payment_methods": []},
+                    "book_token": {"value": "token"},
+                    "payment": {"amounts": {"deposit_fee": deposit_fee}},
+                }))
+                .is_err()
+            );
+        }
+    }
+}
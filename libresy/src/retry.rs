@@ -0,0 +1,133 @@
+use std::{collections::HashMap, time::Duration};
+
+use rand::Rng;
+
+use crate::{error::ResyError, Endpoint};
+
+/// Controls how failed requests to an endpoint are retried. Delays grow exponentially from
+/// `base_delay` up to `max_delay` with full jitter so that several clients backing off at
+/// the same time don't retry in lockstep. A Retry-After sent by Resy takes priority over
+/// the computed delay, but one longer than `max_delay` isn't waited out: the request fails
+/// with [`ResyError::RateLimited`] instead.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Policy that never retries.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 0,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    pub fn exponential(max_retries: u32, base_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay,
+            max_delay: base_delay * 16,
+        }
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> RetryPolicy {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// How long to wait before retrying after the given (zero-based) attempt failed,
+    /// `None` if Resy asked to wait longer than `max_delay`.
+    pub(crate) fn delay(&self, attempt: u32, error: &ResyError) -> Option<Duration> {
+        if let ResyError::RateLimited {
+            retry_after: Some(retry_after),
+        } = error
+        {
+            return (*retry_after <= self.max_delay).then_some(*retry_after);
+        }
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        if ceiling.is_zero() {
+            return Some(ceiling);
+        }
+        Some(rand::thread_rng().gen_range(Duration::ZERO..=ceiling))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::exponential(2, Duration::from_millis(200)).max_delay(Duration::from_secs(2))
+    }
+}
+
+/// Retry policies for every endpoint, falling back to a default policy for endpoints
+/// without one. Whatever the policy, a booking is only retried when Resy can't have
+/// processed it, see [`ResyError::is_retryable`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RetryPolicies {
+    pub default: RetryPolicy,
+    pub endpoints: HashMap<Endpoint, RetryPolicy>,
+}
+
+impl RetryPolicies {
+    pub fn get(&self, endpoint: Endpoint) -> &RetryPolicy {
+        self.endpoints.get(&endpoint).unwrap_or(&self.default)
+    }
+}
+
+impl ResyError {
+    /// Whether a request that failed with this error can be safely sent again. Requests to
+    /// non-idempotent endpoints (booking) are only retried when Resy is known to not have
+    /// processed them: the connection was never made or Resy throttled the request.
+    pub(crate) fn is_retryable(&self, endpoint: Endpoint) -> bool {
+        match self {
+            ResyError::RateLimited { .. } => true,
+            ResyError::Network(e) => endpoint.is_idempotent() || e.is_connect(),
+            ResyError::Api { status, .. } => endpoint.is_idempotent() && *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delays() {
+        let policy = RetryPolicy::exponential(5, Duration::from_millis(100))
+            .max_delay(Duration::from_millis(500));
+        let error = ResyError::Api {
+            status: 503,
+            code: None,
+            message: "Service Unavailable".to_owned(),
+        };
+        for attempt in 0..5 {
+            let delay = policy.delay(attempt, &error).unwrap();
+            let ceiling = Duration::from_millis(100 * 2u64.pow(attempt)).min(policy.max_delay);
+            assert!(delay <= ceiling);
+        }
+
+        let rate_limited = ResyError::RateLimited {
+            retry_after: Some(Duration::from_secs(3)),
+        };
+        assert_eq!(policy.delay(0, &rate_limited), None);
+        let rate_limited = ResyError::RateLimited {
+            retry_after: Some(Duration::from_millis(300)),
+        };
+        assert_eq!(
+            policy.delay(0, &rate_limited),
+            Some(Duration::from_millis(300))
+        );
+
+        // Server errors while booking might mean the booking went through
+        assert!(error.is_retryable(Endpoint::Find));
+        assert!(!error.is_retryable(Endpoint::Book));
+        assert!(rate_limited.is_retryable(Endpoint::Book));
+    }
+}
@@ -0,0 +1,231 @@
+use std::{cmp::Ordering, ops::RangeInclusive, str::FromStr};
+
+use crate::{
+    error::ResyError,
+    matching::{contains_words, normalize},
+    resy_data::{GeoFilter, RestaurantSearchResult},
+};
+
+/// Number of hits asked for per page unless told otherwise.
+pub const DEFAULT_PAGE_SIZE: u16 = 20;
+
+/// Order to put search hits in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchSort {
+    /// The order Resy returned them in.
+    #[default]
+    Relevance,
+    /// Closest to the center of the search area first, hits without a location last.
+    Distance,
+    /// Highest rated first, unrated hits last.
+    Rating,
+    /// Alphabetically, ignoring case.
+    Name,
+}
+
+impl FromStr for SearchSort {
+    type Err = ResyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "relevance" => Ok(SearchSort::Relevance),
+            "distance" => Ok(SearchSort::Distance),
+            "rating" => Ok(SearchSort::Rating),
+            "name" => Ok(SearchSort::Name),
+            _ => Err(ResyError::InvalidConfig(format!(
+                "unknown sort order {s}, expected relevance, distance, rating or name"
+            ))),
+        }
+    }
+}
+
+/// A venue search with the filters to apply to its hits. Resy only searches by name and
+/// area so the filters are applied to each page of hits as it comes in.
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub query: String,
+    pub geo: GeoFilter,
+    pub per_page: u16,
+    /// Only hits serving the cuisine, e.g. "italian".
+    pub cuisine: Option<String>,
+    /// Only hits priced in the range, from 1 to 4 dollar signs.
+    pub price_range: Option<RangeInclusive<u8>>,
+    /// Only hits rated at least this, unrated hits are left out.
+    pub min_rating: Option<f32>,
+    /// Only hits in the neighborhood, e.g. "west village".
+    pub neighborhood: Option<String>,
+    pub sort: SearchSort,
+}
+
+impl SearchQuery {
+    /// Searches the area for venues with names matching the query, an empty query matches
+    /// every venue.
+    pub fn new(geo: GeoFilter, query: &str) -> SearchQuery {
+        SearchQuery {
+            query: query.to_owned(),
+            geo,
+            per_page: DEFAULT_PAGE_SIZE,
+            cuisine: None,
+            price_range: None,
+            min_rating: None,
+            neighborhood: None,
+            sort: SearchSort::default(),
+        }
+    }
+
+    pub fn per_page(mut self, per_page: u16) -> SearchQuery {
+        self.per_page = per_page;
+        self
+    }
+
+    pub fn cuisine(mut self, cuisine: &str) -> SearchQuery {
+        self.cuisine = Some(cuisine.to_owned());
+        self
+    }
+
+    pub fn price_range(mut self, price_range: RangeInclusive<u8>) -> SearchQuery {
+        self.price_range = Some(price_range);
+        self
+    }
+
+    pub fn min_rating(mut self, min_rating: f32) -> SearchQuery {
+        self.min_rating = Some(min_rating);
+        self
+    }
+
+    pub fn neighborhood(mut self, neighborhood: &str) -> SearchQuery {
+        self.neighborhood = Some(neighborhood.to_owned());
+        self
+    }
+
+    /// Order the hits are returned in by [`crate::ResyClient::search_restaurants`]. Streamed
+    /// hits always come in Resy's order since sorting needs every hit.
+    pub fn sort_by(mut self, sort: SearchSort) -> SearchQuery {
+        self.sort = sort;
+        self
+    }
+
+    /// Whether the hit passes every filter of the query.
+    pub fn matches(&self, hit: &RestaurantSearchResult) -> bool {
+        let cuisine = self.cuisine.as_deref().map(normalize);
+        let neighborhood = self.neighborhood.as_deref().map(normalize);
+        cuisine.is_none_or(|cuisine| {
+            hit.cuisine
+                .iter()
+                .any(|c| contains_words(&normalize(c), &cuisine))
+        }) && self.price_range.as_ref().is_none_or(|range| {
+            hit.price_range_id
+                .is_some_and(|price| range.contains(&price))
+        }) && self.min_rating.is_none_or(|min_rating| {
+            hit.rating
+                .as_ref()
+                .is_some_and(|rating| rating.average >= min_rating)
+        }) && neighborhood.is_none_or(|neighborhood| {
+            hit.neighborhood
+                .as_deref()
+                .is_some_and(|n| normalize(n) == neighborhood)
+        })
+    }
+
+    /// Distance in meters from the center of the search area to the hit.
+    pub fn distance_to(&self, hit: &RestaurantSearchResult) -> Option<f64> {
+        hit.geoloc
+            .as_ref()
+            .map(|geo| self.geo.distance_to(geo.lat, geo.lng))
+    }
+
+    /// Puts the hits in the query's sort order. The sort is stable so hits that compare
+    /// equal stay in Resy's order.
+    pub fn sort(&self, hits: &mut [RestaurantSearchResult]) {
+        // Missing values sort after every present one
+        fn last_if_missing<T>(
+            a: Option<T>,
+            b: Option<T>,
+            cmp: impl Fn(T, T) -> Ordering,
+        ) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => cmp(a, b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        }
+        match self.sort {
+            SearchSort::Relevance => {}
+            SearchSort::Distance => hits.sort_by(|a, b| {
+                last_if_missing(self.distance_to(a), self.distance_to(b), |a, b| {
+                    a.total_cmp(&b)
+                })
+            }),
+            SearchSort::Rating => hits.sort_by(|a, b| {
+                let rating = |hit: &RestaurantSearchResult| hit.rating.as_ref().map(|r| r.average);
+                last_if_missing(rating(a), rating(b), |a, b| b.total_cmp(&a))
+            }),
+            SearchSort::Name => hits.sort_by_key(|hit| hit.name.to_lowercase()),
+        }
+    }
+}
+
+/// One page of search hits.
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    /// Hits on the page that pass the query's filters.
+    pub hits: Vec<RestaurantSearchResult>,
+    /// Starts at 1.
+    pub page: u32,
+    /// Pages of hits before filtering.
+    pub total_pages: u32,
+    /// Hits across every page before filtering, so usually more than the filtered hits.
+    pub total_hits: u32,
+}
+
+impl SearchPage {
+    pub fn has_next(&self) -> bool {
+        self.page < self.total_pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(name: &str, price: u8, rating: f32, lat: f64) -> RestaurantSearchResult {
+        serde_json::from_value(serde_json::json!({
+            "objectID": name,
+            "name": name,
+            "neighborhood": "West Village",
+            "cuisine": ["Italian", "Pizza"],
+            "price_range_id": price,
+            "rating": {"average": rating, "count": 10},
+            "_geoloc": {"lat": lat, "lng": 0.0},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_search_query() {
+        let query = SearchQuery::new(GeoFilter::new(0.0, 0.0, 5000), "")
+            .cuisine("pizza")
+            .price_range(1..=2)
+            .min_rating(4.0)
+            .neighborhood("west-village");
+        assert!(query.matches(&hit("Joe's", 1, 4.5, 0.0)));
+        assert!(!query.matches(&hit("Carbone", 4, 4.9, 0.0)));
+        assert!(!query.matches(&hit("Bad Slice", 1, 3.0, 0.0)));
+
+        let mut hits = vec![
+            hit("b", 1, 4.0, 0.02),
+            hit("C", 1, 4.8, 0.01),
+            hit("a", 1, 4.5, 0.03),
+        ];
+        let names = |hits: &[RestaurantSearchResult]| -> Vec<String> {
+            hits.iter().map(|h| h.name.clone()).collect()
+        };
+        query.clone().sort_by(SearchSort::Name).sort(&mut hits);
+        assert_eq!(names(&hits), ["a", "b", "C"]);
+        query.clone().sort_by(SearchSort::Rating).sort(&mut hits);
+        assert_eq!(names(&hits), ["C", "a", "b"]);
+        query.sort_by("distance".parse().unwrap()).sort(&mut hits);
+        assert_eq!(names(&hits), ["C", "b", "a"]);
+    }
+}
@@ -0,0 +1,71 @@
+use common::{next_tuesday, spawn_client, spawn_sim};
+use libresy::{ResyClientBuilder, ResyError};
+use resy_sim::scenario::Scenario;
+
+mod common;
+
+#[tokio::test]
+async fn test_login_refresh() {
+    let mut scenario = Scenario::default();
+    scenario.auth.email = Some("sim@example.com".to_owned());
+    scenario.auth.password = Some("hunter2".to_owned());
+    scenario.auth.expires_after_secs = Some(0.2);
+    let base_url = spawn_sim(scenario).await;
+    let resy_client = ResyClientBuilder::new("key".to_owned(), String::new())
+        .credentials("sim@example.com".to_owned(), "hunter2".to_owned())
+        .base_url(&base_url)
+        .build()
+        .unwrap();
+
+    assert!(resy_client.get_notifications().await.is_ok());
+    let first_token = resy_client.auth_token().unwrap();
+    // The token expires, the client should log in again on its own
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert!(resy_client.get_notifications().await.is_ok());
+    assert_ne!(resy_client.auth_token().unwrap(), first_token);
+
+    let bad_client = ResyClientBuilder::new("key".to_owned(), String::new())
+        .credentials("sim@example.com".to_owned(), "wrong".to_owned())
+        .base_url(&base_url)
+        .build()
+        .unwrap();
+    assert!(matches!(
+        bad_client.get_notifications().await,
+        Err(ResyError::Unauthorized { status: 419 })
+    ));
+}
+
+#[tokio::test]
+async fn test_expired_token() {
+    let mut scenario = Scenario::default();
+    scenario.auth.token = Some("token".to_owned());
+    scenario.auth.expires_after_secs = Some(0.0);
+    let resy_client = spawn_client(scenario).await;
+    let date = next_tuesday();
+    let slots = resy_client.get_reservations("65615", &date, 2).await;
+    assert!(matches!(
+        slots,
+        Err(ResyError::Unauthorized { status: 419 })
+    ));
+}
+
+#[tokio::test]
+async fn test_concurrent_login() {
+    let mut scenario = Scenario::default();
+    scenario.auth.token = Some("expired".to_owned());
+    scenario.auth.email = Some("sim@example.com".to_owned());
+    scenario.auth.password = Some("hunter2".to_owned());
+    let base_url = spawn_sim(scenario).await;
+    let resy_client = ResyClientBuilder::new("key".to_owned(), "token".to_owned())
+        .credentials("sim@example.com".to_owned(), "hunter2".to_owned())
+        .base_url(&base_url)
+        .build()
+        .unwrap();
+
+    // Every login replaces the sim's token, so requests that each logged in would
+    // invalidate each other's tokens
+    let requests = (0..8).map(|_| resy_client.get_notifications());
+    for result in futures::future::join_all(requests).await {
+        assert!(result.is_ok());
+    }
+}
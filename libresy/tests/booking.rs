@@ -0,0 +1,177 @@
+use common::{next_tuesday, spawn_client};
+use libresy::{
+    resy_data::{BookingSpecs, DayStatus, PaymentSelector},
+    ResyError,
+};
+use resy_sim::scenario::Scenario;
+
+mod common;
+
+#[tokio::test]
+async fn test_booking_flow() {
+    let mut scenario = Scenario::default();
+    scenario.venues[0].book_failures = 2;
+    let mut resy_client = spawn_client(scenario).await;
+    resy_client.load_config().await.unwrap();
+
+    let city = resy_client
+        .get_restaurant_city_config("philadelphia", "US")
+        .unwrap();
+    let venue = resy_client
+        .find_restaurant_by_name(&city, "stella")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(venue.object_id, "65615");
+
+    let date = next_tuesday();
+    let availability = resy_client
+        .get_venue_availability(&venue.object_id, &date, 2)
+        .await
+        .unwrap();
+    assert_eq!(availability.venue.name, "Pizzeria Stella");
+    let slots = availability.slots;
+    assert_eq!(slots.len(), 7);
+    let details = resy_client.get_venue(&venue.object_id).await.unwrap();
+    assert_eq!(
+        details.location.url_slug.as_deref(),
+        Some("philadelphia-pa")
+    );
+    assert!(matches!(
+        resy_client.get_venue("1").await,
+        Err(ResyError::VenueNotFound(_))
+    ));
+
+    let details = resy_client
+        .get_reservation_details(&slots[0], &date, 2)
+        .await
+        .unwrap();
+    let payment = details.get_payment_id().unwrap();
+    let specs = BookingSpecs::for_slot(&slots[0], 2).unwrap();
+
+    // The token has everything needed to get the details without finding the slot
+    let token = slots[0].config.rgs_token().unwrap();
+    assert_eq!(token.party_size, 2);
+    let token_details = resy_client
+        .get_reservation_details_for_token(&token)
+        .await
+        .unwrap();
+    assert_eq!(token_details.book_token.value, details.book_token.value);
+    assert_eq!(BookingSpecs::from(&token), specs);
+    // The scenario fails the first two booking attempts
+    for _ in 0..2 {
+        let booking = resy_client
+            .book_restaurant(&details, &specs, &payment)
+            .await;
+        assert!(matches!(booking, Err(ResyError::BookingConflict(_))));
+    }
+    let confirmation = resy_client
+        .book_restaurant(&details, &specs, &payment)
+        .await
+        .unwrap();
+    assert_eq!(confirmation.venue_id, "65615");
+    assert_eq!(confirmation.date, date);
+
+    let upcoming = resy_client.get_upcoming_reservations().await.unwrap();
+    assert_eq!(upcoming.len(), 1);
+    resy_client
+        .cancel_reservation(&confirmation.resy_token)
+        .await
+        .unwrap();
+    assert!(resy_client
+        .get_upcoming_reservations()
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_payment_methods() {
+    let mut scenario = Scenario::default();
+    let mut second_card = scenario.payment_methods[0].clone();
+    second_card.id = 7654321;
+    second_card.last4 = "1881".to_owned();
+    second_card.is_default = false;
+    scenario.payment_methods.push(second_card);
+    let resy_client = spawn_client(scenario).await;
+
+    let payment_methods = resy_client.get_payment_methods().await.unwrap();
+    assert_eq!(payment_methods.len(), 2);
+    let card = resy_client
+        .check_payment_method(&PaymentSelector::Last4("1881".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(card.id, 7654321);
+
+    let date = next_tuesday();
+    let slots = resy_client
+        .get_reservations("65615", &date, 2)
+        .await
+        .unwrap();
+    let details = resy_client
+        .get_reservation_details(&slots[0], &date, 2)
+        .await
+        .unwrap();
+    let payment = details
+        .select_payment_method(&PaymentSelector::Default)
+        .unwrap();
+    assert_eq!(payment.last4.as_deref(), Some("4242"));
+    let specs = BookingSpecs::for_slot(&slots[0], 2).unwrap();
+    resy_client
+        .book_restaurant(&details, &specs, &card)
+        .await
+        .unwrap();
+
+    // Without a card the check fails instead of the booking
+    let mut scenario = Scenario::default();
+    scenario.payment_methods.clear();
+    let resy_client = spawn_client(scenario).await;
+    assert!(matches!(
+        resy_client
+            .check_payment_method(&PaymentSelector::Default)
+            .await,
+        Err(ResyError::NoPaymentMethod)
+    ));
+}
+
+#[tokio::test]
+async fn test_availability_calendar() {
+    let mut scenario = Scenario::default();
+    scenario.venues[0].slots.truncate(1);
+    scenario.venues[0].slots[0].quantity = 1;
+    let resy_client = spawn_client(scenario).await;
+
+    // Book the only table on Tuesday, the venue is closed on Mondays
+    let tuesday = next_tuesday();
+    let monday = tuesday.pred_opt().unwrap();
+    let sunday = monday.pred_opt().unwrap();
+    let slots = resy_client
+        .get_reservations("65615", &tuesday, 2)
+        .await
+        .unwrap();
+    let details = resy_client
+        .get_reservation_details(&slots[0], &tuesday, 2)
+        .await
+        .unwrap();
+    let payment = details.get_payment_id().unwrap();
+    let specs = BookingSpecs::for_slot(&slots[0], 2).unwrap();
+    resy_client
+        .book_restaurant(&details, &specs, &payment)
+        .await
+        .unwrap();
+
+    let calendar = resy_client
+        .get_availability_calendar("65615", 2, &sunday, &tuesday)
+        .await
+        .unwrap();
+    assert_eq!(calendar.days.len(), 3);
+    assert_eq!(calendar.status_on(sunday), DayStatus::Available);
+    assert_eq!(calendar.status_on(monday), DayStatus::Closed);
+    assert_eq!(calendar.status_on(tuesday), DayStatus::SoldOut);
+    assert!(matches!(
+        resy_client
+            .get_availability_calendar("1", 2, &sunday, &tuesday)
+            .await,
+        Err(ResyError::VenueNotFound(_))
+    ));
+}
@@ -0,0 +1,20 @@
+use chrono::TimeDelta;
+use common::spawn_client;
+use resy_sim::scenario::Scenario;
+
+mod common;
+
+#[tokio::test]
+async fn test_clock_offset() {
+    let scenario = Scenario {
+        clock_offset_ms: -1300,
+        ..Scenario::default()
+    };
+    let resy_client = spawn_client(scenario).await;
+
+    let clock = resy_client.measure_clock_offset(4).await.unwrap();
+    // The sim stamps responses exactly so the real offset is always within the bounds,
+    // however long the requests took
+    let error = clock.offset - TimeDelta::milliseconds(-1300);
+    assert!(error.abs() <= clock.uncertainty + TimeDelta::milliseconds(5));
+}
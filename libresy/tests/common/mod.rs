@@ -0,0 +1,33 @@
+//! Helpers shared by the tests running the client against resy-sim.
+#![allow(dead_code)]
+
+use chrono::{NaiveDate, Weekday};
+use libresy::{ResyClient, ResyClientBuilder};
+use resy_sim::scenario::Scenario;
+
+/// Serves the scenario on a free port, returning the base URL to point a client at.
+pub async fn spawn_sim(scenario: Scenario) -> String {
+    let addr = resy_sim::spawn("127.0.0.1:0".parse().unwrap(), scenario)
+        .await
+        .unwrap();
+    format!("http://{}", addr)
+}
+
+/// Builder for a client talking to the sim at the base URL. The config cache is off so
+/// tests never touch the user's cache.
+pub fn client_for(base_url: &str) -> ResyClientBuilder {
+    ResyClientBuilder::new("key".to_owned(), "token".to_owned())
+        .base_url(base_url)
+        .no_cache()
+}
+
+/// Client for a fresh sim serving the scenario.
+pub async fn spawn_client(scenario: Scenario) -> ResyClient {
+    client_for(&spawn_sim(scenario).await).build().unwrap()
+}
+
+/// First Tuesday after today, a day the default venue is open while the Monday before
+/// it is closed.
+pub fn next_tuesday() -> NaiveDate {
+    resy_sim::next_weekday(Weekday::Tue)
+}
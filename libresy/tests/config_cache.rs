@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use common::spawn_sim;
+use libresy::{cache::ConfigCache, ResyClientBuilder, RetryPolicy};
+use resy_sim::scenario::Scenario;
+
+mod common;
+
+#[tokio::test]
+async fn test_config_cache_refresh() {
+    let base_url = spawn_sim(Scenario::default()).await;
+    let cache_dir = std::env::temp_dir().join(format!("libresy-cache-{}", std::process::id()));
+    let cache = ConfigCache::new(&cache_dir);
+    cache.clear().unwrap();
+    let builder = || {
+        ResyClientBuilder::new("key".to_owned(), "token".to_owned())
+            .base_url(&base_url)
+            .cache_dir(&cache_dir)
+            .cache_ttl(Duration::ZERO)
+    };
+
+    let mut resy_client = builder().build().unwrap();
+    resy_client.load_config().await.unwrap();
+    let first = cache.info().unwrap().unwrap().metadata.unwrap();
+    assert!(first.etag.is_some());
+
+    // Every load sees a stale cache and refreshes it, the sim says nothing changed so only
+    // the fetched-at time moves
+    let mut resy_client = builder().build().unwrap();
+    resy_client.load_config().await.unwrap();
+    assert!(resy_client
+        .get_restaurant_city_config("philadelphia", "US")
+        .is_ok());
+    resy_client.finish_config_refresh().await.unwrap();
+    let refreshed = cache.info().unwrap().unwrap().metadata.unwrap();
+    assert!(refreshed.fetched_at > first.fetched_at);
+    assert_eq!(refreshed.etag, first.etag);
+
+    // A stale cache is used right away even when Resy can't be reached, the failed
+    // refresh is reported separately
+    let mut resy_client = ResyClientBuilder::new("key".to_owned(), "token".to_owned())
+        .base_url("http://127.0.0.1:1")
+        .cache_dir(&cache_dir)
+        .cache_ttl(Duration::ZERO)
+        .default_retry_policy(RetryPolicy::none())
+        .build()
+        .unwrap();
+    resy_client.load_config().await.unwrap();
+    assert!(resy_client
+        .get_restaurant_city_config("philadelphia", "US")
+        .is_ok());
+    assert!(resy_client.finish_config_refresh().await.is_err());
+    assert_eq!(
+        cache.info().unwrap().unwrap().metadata.unwrap().fetched_at,
+        refreshed.fetched_at
+    );
+
+    cache.clear().unwrap();
+    assert!(cache.info().unwrap().is_none());
+}
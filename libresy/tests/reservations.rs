@@ -0,0 +1,24 @@
+use common::spawn_client;
+use resy_sim::scenario::Scenario;
+
+mod common;
+
+#[tokio::test]
+async fn test_reservation_pages() {
+    let resy_client = spawn_client(Scenario {
+        past_reservations: 250,
+        ..Scenario::default()
+    })
+    .await;
+
+    let past = resy_client.get_past_reservations().await.unwrap();
+    assert_eq!(past.len(), 250);
+    assert_eq!(past[0].resy_token, "sim-resy-0");
+    assert_eq!(past[249].resy_token, "sim-resy-249");
+    assert_eq!(past[0].venue.name.as_deref(), Some("Pizzeria Stella"));
+    assert!(resy_client
+        .get_upcoming_reservations()
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+
+use common::{client_for, next_tuesday, spawn_sim};
+use libresy::{
+    resy_data::{BookingSpecs, GeoFilter},
+    search::SearchQuery,
+    Endpoint, ResyError, RetryPolicy,
+};
+use resy_sim::scenario::{FailureScenario, Scenario};
+
+mod common;
+
+fn failure(path: &str, count: u32, status: u16, retry_after_secs: Option<u64>) -> FailureScenario {
+    FailureScenario {
+        path: path.to_owned(),
+        count,
+        status,
+        retry_after_secs,
+    }
+}
+
+#[tokio::test]
+async fn test_retry_idempotent() {
+    let date = next_tuesday();
+    let scenario = Scenario {
+        failures: vec![
+            failure("/4/find", 2, 503, None),
+            failure("/3/details", 1, 502, None),
+            failure("/3/venuesearch/search", 1, 500, None),
+        ],
+        ..Scenario::default()
+    };
+    let resy_client = client_for(&spawn_sim(scenario.clone()).await)
+        .default_retry_policy(RetryPolicy::exponential(2, Duration::from_millis(10)))
+        .build()
+        .unwrap();
+
+    let slots = resy_client
+        .get_reservations("65615", &date, 2)
+        .await
+        .unwrap();
+    assert!(!slots.is_empty());
+    assert!(resy_client
+        .get_reservation_details(&slots[0], &date, 2)
+        .await
+        .is_ok());
+    let query = SearchQuery::new(GeoFilter::new(39.95258, -75.16522, 5000), "stella");
+    assert_eq!(
+        resy_client.search_restaurants(&query).await.unwrap().len(),
+        1
+    );
+
+    // One retry isn't enough to get past two failures
+    let resy_client = client_for(&spawn_sim(scenario).await)
+        .default_retry_policy(RetryPolicy::exponential(1, Duration::from_millis(10)))
+        .build()
+        .unwrap();
+    assert!(matches!(
+        resy_client.get_reservations("65615", &date, 2).await,
+        Err(ResyError::Api { status: 503, .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_retry_after() {
+    let date = next_tuesday();
+    let policy =
+        RetryPolicy::exponential(2, Duration::from_millis(10)).max_delay(Duration::from_secs(2));
+
+    let scenario = Scenario {
+        failures: vec![failure("/4/find", 1, 429, Some(1))],
+        ..Scenario::default()
+    };
+    let resy_client = client_for(&spawn_sim(scenario).await)
+        .default_retry_policy(policy.clone())
+        .build()
+        .unwrap();
+    let started = Instant::now();
+    assert!(resy_client
+        .get_reservations("65615", &date, 2)
+        .await
+        .is_ok());
+    assert!(started.elapsed() >= Duration::from_secs(1));
+
+    // Waiting longer than the policy allows fails right away instead
+    let scenario = Scenario {
+        failures: vec![failure("/4/find", 1, 429, Some(30))],
+        ..Scenario::default()
+    };
+    let resy_client = client_for(&spawn_sim(scenario).await)
+        .default_retry_policy(policy)
+        .build()
+        .unwrap();
+    let started = Instant::now();
+    assert!(matches!(
+        resy_client.get_reservations("65615", &date, 2).await,
+        Err(ResyError::RateLimited { retry_after: Some(d) }) if d == Duration::from_secs(30)
+    ));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_booking_not_resent() {
+    let date = next_tuesday();
+    let scenario = Scenario {
+        failures: vec![failure("/3/book", 1, 500, None)],
+        ..Scenario::default()
+    };
+    let resy_client = client_for(&spawn_sim(scenario).await)
+        .retry_policy(
+            Endpoint::Book,
+            RetryPolicy::exponential(3, Duration::from_millis(10)),
+        )
+        .build()
+        .unwrap();
+
+    let slots = resy_client
+        .get_reservations("65615", &date, 2)
+        .await
+        .unwrap();
+    let details = resy_client
+        .get_reservation_details(&slots[0], &date, 2)
+        .await
+        .unwrap();
+    let payment = details.get_payment_id().unwrap();
+    let specs = BookingSpecs::for_slot(&slots[0], 2).unwrap();
+    // The booking may have gone through, sending it again could book twice
+    assert!(matches!(
+        resy_client
+            .book_restaurant(&details, &specs, &payment)
+            .await,
+        Err(ResyError::Api { status: 500, .. })
+    ));
+    assert!(resy_client
+        .get_upcoming_reservations()
+        .await
+        .unwrap()
+        .is_empty());
+    // Only the one request was sent, so the next one goes through
+    assert!(resy_client
+        .book_restaurant(&details, &specs, &payment)
+        .await
+        .is_ok());
+}
@@ -0,0 +1,244 @@
+use chrono::NaiveTime;
+use common::{client_for, next_tuesday, spawn_client, spawn_sim};
+use futures::TryStreamExt;
+use libresy::{
+    resy_data::{DiscoverQuery, GeoFilter},
+    search::{SearchQuery, SearchSort},
+    ResyError,
+};
+use resy_sim::scenario::{Scenario, VenueScenario};
+
+mod common;
+
+#[tokio::test]
+async fn test_strict_match() {
+    let mut scenario = Scenario::default();
+    let stella = scenario.venues[0].clone();
+    for (id, name, neighborhood) in [
+        (1, "Lucali", "Carroll Gardens"),
+        (2, "Lucali", "Miami Beach"),
+        (3, "Lucali Bar", "Carroll Gardens"),
+    ] {
+        scenario.venues.push(VenueScenario {
+            id,
+            name: name.to_owned(),
+            neighborhood: neighborhood.to_owned(),
+            ..stella.clone()
+        });
+    }
+    let base_url = spawn_sim(scenario).await;
+    let mut resy_client = client_for(&base_url).build().unwrap();
+    resy_client.load_config().await.unwrap();
+    let city = resy_client
+        .get_restaurant_city_config("philadelphia", "US")
+        .unwrap();
+
+    let ranked = resy_client
+        .find_restaurants_by_name(&city, "lucali")
+        .await
+        .unwrap();
+    assert_eq!(ranked.len(), 3);
+    assert!(ranked[0].is_exact() && ranked[1].is_exact());
+    assert_eq!(ranked[2].restaurant.name, "Lucali Bar");
+
+    // Both Lucalis are exact matches, so neither is picked
+    match resy_client.find_restaurant_by_name(&city, "lucali").await {
+        Err(ResyError::AmbiguousRestaurant { candidates, .. }) => {
+            let ids: Vec<&str> = candidates.iter().map(|c| c.object_id.as_str()).collect();
+            assert_eq!(ids, ["1", "2"]);
+        }
+        result => panic!("expected an ambiguous match, got {:?}", result),
+    }
+    let closest = resy_client
+        .find_restaurant_by_name(&city, "lucali ba")
+        .await
+        .unwrap();
+    assert_eq!(closest.unwrap().name, "Lucali Bar");
+
+    let strict_client = client_for(&base_url).strict_match().build().unwrap();
+    assert!(matches!(
+        strict_client.find_restaurant_by_name(&city, "lucali").await,
+        Err(ResyError::AmbiguousRestaurant { .. })
+    ));
+    let bar = strict_client
+        .find_restaurant_by_name(&city, "LUCALI BAR")
+        .await
+        .unwrap();
+    assert_eq!(bar.unwrap().object_id, "3");
+    assert!(strict_client
+        .find_restaurant_by_name(&city, "stella")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_discover() {
+    let mut scenario = Scenario::default();
+    let stella = scenario.venues[0].clone();
+    scenario.venues.push(VenueScenario {
+        id: 1,
+        name: "Lucali".to_owned(),
+        city: "new-york-ny".to_owned(),
+        ..stella.clone()
+    });
+    scenario.venues.push(VenueScenario {
+        id: 2,
+        name: "Booked Solid".to_owned(),
+        slots: Vec::new(),
+        ..stella
+    });
+    let mut resy_client = spawn_client(scenario).await;
+    resy_client.load_config().await.unwrap();
+    let city = resy_client
+        .get_restaurant_city_config("philadelphia", "US")
+        .unwrap();
+
+    let tuesday = next_tuesday();
+    let time = |t| NaiveTime::parse_from_str(t, "%H:%M").unwrap();
+    let query = DiscoverQuery::new(GeoFilter::around(&city, 10_000), tuesday, 2)
+        .time_window(time("19:00"), time("20:00"));
+    let venues = resy_client.discover(&query).await.unwrap();
+    assert_eq!(venues.len(), 1);
+    assert_eq!(venues[0].restaurant.name, "Pizzeria Stella");
+    assert_eq!(venues[0].slots.len(), 2);
+
+    // The venues are closed on Mondays
+    let monday = tuesday.pred_opt().unwrap();
+    let query = DiscoverQuery::new(GeoFilter::around(&city, 10_000), monday, 2);
+    assert!(resy_client.discover(&query).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_discover_limit() {
+    let mut scenario = Scenario::default();
+    let stella = scenario.venues.remove(0);
+    let lunch_only: Vec<_> = stella
+        .slots
+        .iter()
+        .filter(|s| s.time < NaiveTime::from_hms_opt(15, 0, 0).unwrap())
+        .cloned()
+        .collect();
+    for id in 1..=6 {
+        scenario.venues.push(VenueScenario {
+            id,
+            name: format!("Venue {id}"),
+            // Every other venue only has tables outside the window
+            slots: if id % 2 == 0 {
+                stella.slots.clone()
+            } else {
+                lunch_only.clone()
+            },
+            ..stella.clone()
+        });
+    }
+    let mut resy_client = spawn_client(scenario).await;
+    resy_client.load_config().await.unwrap();
+    let city = resy_client
+        .get_restaurant_city_config("philadelphia", "US")
+        .unwrap();
+
+    let tuesday = next_tuesday();
+    let time = |t| NaiveTime::parse_from_str(t, "%H:%M").unwrap();
+    let query = DiscoverQuery::new(GeoFilter::around(&city, 10_000), tuesday, 2)
+        .time_window(time("19:00"), time("20:00"))
+        .limit(2);
+    let venues = resy_client.discover(&query).await.unwrap();
+    let names: Vec<_> = venues.iter().map(|v| v.restaurant.name.as_str()).collect();
+    assert_eq!(names, ["Venue 2", "Venue 4"]);
+
+    let query = query.limit(5);
+    assert_eq!(resy_client.discover(&query).await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_search_pages() {
+    let mut scenario = Scenario::default();
+    let stella = scenario.venues[0].clone();
+    for id in 1..=25 {
+        scenario.venues.push(VenueScenario {
+            id,
+            name: format!("Venue {id}"),
+            cuisine: if id % 5 == 0 { "Thai" } else { "Italian" }.to_owned(),
+            rating: id as f32 / 5.0,
+            ..stella.clone()
+        });
+    }
+    let mut resy_client = spawn_client(scenario).await;
+    resy_client.load_config().await.unwrap();
+    let city = resy_client
+        .get_restaurant_city_config("philadelphia", "US")
+        .unwrap();
+
+    let query = SearchQuery::new(GeoFilter::around(&city, 10_000), "").per_page(10);
+    let page = resy_client
+        .search_restaurants_page(&query, 1)
+        .await
+        .unwrap();
+    assert_eq!(page.hits.len(), 10);
+    assert_eq!((page.total_pages, page.total_hits), (3, 26));
+    let streamed: Vec<_> = resy_client
+        .search_restaurants_stream(&query)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(streamed.len(), 26);
+
+    let query = query
+        .cuisine("thai")
+        .min_rating(3.0)
+        .sort_by(SearchSort::Rating);
+    let names: Vec<String> = resy_client
+        .search_restaurants(&query)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|hit| hit.name)
+        .collect();
+    assert_eq!(names, ["Venue 25", "Venue 20", "Venue 15"]);
+}
+
+#[tokio::test]
+async fn test_search_ignoring_pages() {
+    let mut scenario = Scenario {
+        search_ignores_paging: true,
+        ..Scenario::default()
+    };
+    let stella = scenario.venues[0].clone();
+    let mut lunch_only = stella.slots.clone();
+    lunch_only.retain(|s| s.time < NaiveTime::from_hms_opt(15, 0, 0).unwrap());
+    for id in 1..=25 {
+        scenario.venues.push(VenueScenario {
+            id,
+            name: format!("Venue {id}"),
+            slots: if id % 2 == 0 {
+                stella.slots.clone()
+            } else {
+                lunch_only.clone()
+            },
+            ..stella.clone()
+        });
+    }
+    let mut resy_client = spawn_client(scenario).await;
+    resy_client.load_config().await.unwrap();
+    let city = resy_client
+        .get_restaurant_city_config("philadelphia", "US")
+        .unwrap();
+
+    // Every page is the first one, so paging stops once it repeats
+    let query = SearchQuery::new(GeoFilter::around(&city, 10_000), "").per_page(10);
+    let streamed: Vec<_> = resy_client
+        .search_restaurants_stream(&query)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(streamed.len(), 10);
+
+    let tuesday = next_tuesday();
+    let time = |t| NaiveTime::parse_from_str(t, "%H:%M").unwrap();
+    let query = DiscoverQuery::new(GeoFilter::around(&city, 10_000), tuesday, 2)
+        .time_window(time("19:00"), time("20:00"))
+        .limit(15);
+    // Pizzeria Stella and the even venues on the first page
+    assert_eq!(resy_client.discover(&query).await.unwrap().len(), 8);
+}
@@ -0,0 +1,38 @@
+use std::time::{Duration, Instant};
+
+use common::{client_for, spawn_client, spawn_sim};
+use libresy::{Endpoint, RateLimit, RateLimiter};
+use resy_sim::scenario::Scenario;
+
+mod common;
+
+#[tokio::test]
+async fn test_warm_up() {
+    let resy_client = spawn_client(Scenario::default()).await;
+    resy_client.warm_up(4).await.unwrap();
+
+    // Only the connections matter, it's fine for Resy to turn the requests away
+    let mut scenario = Scenario::default();
+    scenario.auth.token = Some("someone else's".to_owned());
+    spawn_client(scenario).await.warm_up(2).await.unwrap();
+
+    let unreachable = client_for("http://127.0.0.1:1").build().unwrap();
+    assert!(unreachable.warm_up(1).await.is_err());
+}
+
+#[tokio::test]
+async fn test_warm_up_rate_limited() {
+    let rate_limiter = RateLimiter::new().endpoint(
+        Endpoint::Config,
+        RateLimit::new(1, Duration::from_millis(200)),
+    );
+    let resy_client = client_for(&spawn_sim(Scenario::default()).await)
+        .rate_limiter(rate_limiter)
+        .build()
+        .unwrap();
+
+    // The first request uses up the budget, the other two each wait for the next token
+    let started = Instant::now();
+    resy_client.warm_up(3).await.unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(350));
+}
@@ -1,8 +1,11 @@
+use std::env;
+
 use anyhow::anyhow;
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use libresy::{
+    profile,
     resy_data::{ResyNotification, ResyNotificationSpec},
-    ResyClientBuilder,
+    Profile,
 };
 
 #[derive(Parser)]
@@ -13,10 +16,24 @@ struct Cli {
     no_cache: bool,
     #[command(flatten)]
     verbose: clap_verbosity_flag::Verbosity,
+    /// Name of a profile in profiles.toml to take credentials from. Credentials passed as
+    /// flags or env vars take priority over the profile's.
+    #[arg(long, env = "RESY_PROFILE")]
+    profile: Option<String>,
     #[arg(short = 'k', long, env, hide_env_values = true)]
-    api_key: String,
+    api_key: Option<String>,
     #[arg(short, long, env, hide_env_values = true)]
-    auth_token: String,
+    auth_token: Option<String>,
+    /// Email to log in with instead of providing an auth token. The token is refreshed
+    /// automatically when it expires.
+    #[arg(long, env = "RESY_EMAIL")]
+    email: Option<String>,
+    /// Name of the environment variable holding the password for --email.
+    #[arg(long, default_value = "RESY_PASSWORD")]
+    password_env: String,
+    /// Overrides the Resy API base URL, e.g. to point at a local stand-in server.
+    #[arg(long, env = "RESY_BASE_URL")]
+    base_url: Option<String>,
     /// Size of party to get notified for.
     #[arg(short, long, env, default_value_t = 2)]
     party_size: u8,
@@ -86,9 +103,15 @@ fn notifications_filter(notification: &ResyNotification, restaurant_id: &Option<
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    let builder = ResyClientBuilder::new(cli.api_key, cli.auth_token);
-
-    let resy_client = builder.build();
+    let flags = Profile {
+        api_key: cli.api_key.clone(),
+        auth_token: cli.auth_token.clone(),
+        email: cli.email.clone(),
+        password: env::var(&cli.password_env).ok(),
+        base_url: cli.base_url.clone(),
+        ..Profile::default()
+    };
+    let resy_client = profile::client_builder(flags, cli.profile.as_deref())?.build()?;
 
     match &cli.command {
         Commands::List { restaurant_id } => {
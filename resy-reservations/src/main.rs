@@ -0,0 +1,275 @@
+use std::env;
+
+use anyhow::anyhow;
+use clap::{Parser, Subcommand};
+use libresy::{profile, resy_data::UserReservation, Profile, ResyClient};
+
+#[derive(Parser)]
+#[command(author, version, about)]
+struct Cli {
+    #[command(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    /// Name of a profile in profiles.toml to take credentials from. Credentials passed as
+    /// flags or env vars take priority over the profile's.
+    #[arg(long, env = "RESY_PROFILE")]
+    profile: Option<String>,
+    #[arg(short = 'k', long, env, hide_env_values = true)]
+    api_key: Option<String>,
+    #[arg(short, long, env, hide_env_values = true)]
+    auth_token: Option<String>,
+    /// Email to log in with instead of providing an auth token. The token is refreshed
+    /// automatically when it expires.
+    #[arg(long, env = "RESY_EMAIL")]
+    email: Option<String>,
+    /// Name of the environment variable holding the password for --email.
+    #[arg(long, default_value = "RESY_PASSWORD")]
+    password_env: String,
+    /// Overrides the Resy API base URL, e.g. to point at a local stand-in server.
+    #[arg(long, env = "RESY_BASE_URL")]
+    base_url: Option<String>,
+    /// Flag enabling json output instead of human-readable.
+    #[arg(long, action)]
+    json: bool,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// List reservations, upcoming ones by default.
+    List {
+        /// List past reservations instead of upcoming ones.
+        #[arg(long, action)]
+        past: bool,
+        #[arg(long = "id")]
+        restaurant_id: Option<u32>,
+    },
+    /// Show a single reservation.
+    Show {
+        /// Reservation ID or resy token of the reservation.
+        reservation: String,
+    },
+    /// Cancel an upcoming reservation.
+    Cancel {
+        /// Reservation ID or resy token of the reservation.
+        reservation: String,
+    },
+}
+
+fn print_reservation(reservation: &UserReservation, json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(reservation)?);
+        return Ok(());
+    }
+    println!(
+        "Reservation ID: {}, Venue: {} (Resy ID = {}), Date: {} {}, Party size: {}, Table: {}",
+        reservation.reservation_id,
+        reservation.venue.name.as_deref().unwrap_or("Unknown"),
+        reservation.venue.id,
+        reservation.day,
+        reservation.time_slot,
+        reservation.num_seats,
+        reservation
+            .config
+            .as_ref()
+            .map_or("Unknown", |c| c.slot_type.as_str())
+    );
+    Ok(())
+}
+
+fn print_cancellation(reservation: &UserReservation, json: bool) -> anyhow::Result<()> {
+    if json {
+        let cancellation = serde_json::json!({
+            "reservation_id": reservation.reservation_id,
+            "resy_token": reservation.resy_token,
+            "cancelled": true,
+        });
+        println!("{}", serde_json::to_string(&cancellation)?);
+        return Ok(());
+    }
+    println!("Cancelled reservation {}", reservation.reservation_id);
+    Ok(())
+}
+
+/// Lists upcoming or past reservations, only those at the restaurant if one is given.
+async fn list_reservations(
+    resy_client: &ResyClient,
+    past: bool,
+    restaurant_id: Option<u32>,
+) -> anyhow::Result<Vec<UserReservation>> {
+    let reservations = if past {
+        resy_client.get_past_reservations().await?
+    } else {
+        resy_client.get_upcoming_reservations().await?
+    };
+    Ok(reservations
+        .into_iter()
+        .filter(|r| restaurant_id.is_none_or(|id| r.venue.id == id))
+        .collect())
+}
+
+/// Finds a reservation by either its reservation ID or resy token, checking upcoming
+/// reservations first since those are the only ones that can be cancelled. Returns
+/// whether the reservation is upcoming along with it.
+async fn find_reservation(
+    resy_client: &ResyClient,
+    reservation: &str,
+) -> anyhow::Result<(UserReservation, bool)> {
+    let is_match = |r: &UserReservation| {
+        r.resy_token == reservation || r.reservation_id.to_string() == reservation
+    };
+    if let Some(r) = resy_client
+        .get_upcoming_reservations()
+        .await?
+        .into_iter()
+        .find(is_match)
+    {
+        return Ok((r, true));
+    }
+    resy_client
+        .get_past_reservations()
+        .await?
+        .into_iter()
+        .find(is_match)
+        .map(|r| (r, false))
+        .ok_or_else(|| anyhow!("No reservation {} was found", reservation))
+}
+
+/// Cancels an upcoming reservation. Past and already cancelled reservations are refused.
+async fn cancel_reservation(
+    resy_client: &ResyClient,
+    reservation: &str,
+) -> anyhow::Result<UserReservation> {
+    let (r, upcoming) = find_reservation(resy_client, reservation).await?;
+    if !upcoming {
+        return Err(anyhow!(
+            "Reservation {} on {} has already passed or been cancelled",
+            r.reservation_id,
+            r.day
+        ));
+    }
+    resy_client.cancel_reservation(&r.resy_token).await?;
+    Ok(r)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let flags = Profile {
+        api_key: cli.api_key.clone(),
+        auth_token: cli.auth_token.clone(),
+        email: cli.email.clone(),
+        password: env::var(&cli.password_env).ok(),
+        base_url: cli.base_url.clone(),
+        ..Profile::default()
+    };
+    let resy_client = profile::client_builder(flags, cli.profile.as_deref())?.build()?;
+
+    match &cli.command {
+        Commands::List {
+            past,
+            restaurant_id,
+        } => {
+            for r in list_reservations(&resy_client, *past, *restaurant_id).await? {
+                print_reservation(&r, cli.json)?;
+            }
+        }
+        Commands::Show { reservation } => {
+            let (r, _) = find_reservation(&resy_client, reservation).await?;
+            print_reservation(&r, cli.json)?;
+            if !cli.json {
+                println!("Resy token: {}", r.resy_token);
+            }
+        }
+        Commands::Cancel { reservation } => {
+            let r = cancel_reservation(&resy_client, reservation).await?;
+            print_cancellation(&r, cli.json)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Weekday;
+    use libresy::{resy_data::BookingSpecs, ResyClientBuilder};
+    use resy_sim::scenario::Scenario;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_manage_reservations() {
+        let addr = resy_sim::spawn(
+            "127.0.0.1:0".parse().unwrap(),
+            Scenario {
+                past_reservations: 2,
+                ..Scenario::default()
+            },
+        )
+        .await
+        .unwrap();
+        let resy_client = ResyClientBuilder::new("key".to_owned(), "token".to_owned())
+            .base_url(&format!("http://{}", addr))
+            .build()
+            .unwrap();
+
+        let date = resy_sim::next_weekday(Weekday::Tue);
+        let slots = resy_client
+            .get_reservations("65615", &date, 2)
+            .await
+            .unwrap();
+        let details = resy_client
+            .get_reservation_details(&slots[0], &date, 2)
+            .await
+            .unwrap();
+        let confirmation = resy_client
+            .book_restaurant(
+                &details,
+                &BookingSpecs::for_slot(&slots[0], 2).unwrap(),
+                &details.get_payment_id().unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let upcoming = list_reservations(&resy_client, false, None).await.unwrap();
+        assert_eq!(upcoming.len(), 1);
+        assert_eq!(
+            list_reservations(&resy_client, true, None)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(list_reservations(&resy_client, false, Some(1))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Reservations are found by either their id or their token
+        let (by_id, is_upcoming) =
+            find_reservation(&resy_client, &confirmation.reservation_id.to_string())
+                .await
+                .unwrap();
+        assert!(is_upcoming);
+        assert_eq!(by_id.resy_token, confirmation.resy_token);
+        let (past, is_upcoming) = find_reservation(&resy_client, "sim-resy-0").await.unwrap();
+        assert!(!is_upcoming);
+        assert!(find_reservation(&resy_client, "sim-resy-9").await.is_err());
+
+        assert!(cancel_reservation(&resy_client, &past.resy_token)
+            .await
+            .is_err());
+        cancel_reservation(&resy_client, &confirmation.resy_token)
+            .await
+            .unwrap();
+        assert!(list_reservations(&resy_client, false, None)
+            .await
+            .unwrap()
+            .is_empty());
+        // Once cancelled it can't be cancelled again
+        assert!(cancel_reservation(&resy_client, &confirmation.resy_token)
+            .await
+            .is_err());
+    }
+}
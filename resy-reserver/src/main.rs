@@ -1,11 +1,30 @@
+use std::collections::HashSet;
+use std::env;
 use std::fmt::Display;
-use std::time::Duration;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
-use chrono::{Days, Local, NaiveDate, NaiveTime};
-use clap::{builder::PossibleValue, command, Parser, Subcommand, ValueEnum};
-use libresy::resy_data::ReservationSlot;
-use libresy::{ResyClient, ResyClientBuilder};
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use clap::{builder::PossibleValue, Parser, Subcommand, ValueEnum};
+use futures::{future, stream, StreamExt};
+use libresy::clock::ClockOffset;
+use libresy::resy_data::{
+    BookingConfirmation, BookingSpecs, Money, PaymentMethod, PaymentSelector, ReservationDetails,
+    ReservationSlot, RgsToken,
+};
+use libresy::{profile, Profile, ResyClient, ResyError};
+use tokio::sync::Semaphore;
+
+/// How long before the drop Resy's clock is measured again when waiting for a while.
+const CLOCK_RESYNC_LEAD: TimeDelta = TimeDelta::seconds(30);
+/// How long before the drop to start keeping connections to Resy open.
+const WARM_UP_LEAD: TimeDelta = TimeDelta::seconds(10);
+/// How often connections are used while keeping them open, well under the time servers
+/// usually give idle connections.
+const WARM_UP_INTERVAL: TimeDelta = TimeDelta::seconds(3);
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -19,10 +38,24 @@ struct Cli {
     restaurant_id: String,
     #[command(flatten)]
     verbose: clap_verbosity_flag::Verbosity,
+    /// Name of a profile in profiles.toml to take credentials from. Credentials passed as
+    /// flags or env vars take priority over the profile's.
+    #[arg(long, env = "RESY_PROFILE")]
+    profile: Option<String>,
     #[arg(short = 'k', long, env, hide_env_values = true)]
-    api_key: String,
+    api_key: Option<String>,
     #[arg(short, long, env, hide_env_values = true)]
-    auth_token: String,
+    auth_token: Option<String>,
+    /// Email to log in with instead of providing an auth token. The token is refreshed
+    /// automatically when it expires.
+    #[arg(long, env = "RESY_EMAIL")]
+    email: Option<String>,
+    /// Name of the environment variable holding the password for --email.
+    #[arg(long, default_value = "RESY_PASSWORD")]
+    password_env: String,
+    /// Overrides the Resy API base URL, e.g. to point at a local stand-in server.
+    #[arg(long, env = "RESY_BASE_URL")]
+    base_url: Option<String>,
     /// Size of party to find tables for.
     #[arg(short, long, env, default_value_t = 2)]
     party_size: u8,
@@ -39,6 +72,28 @@ struct Cli {
     /// Determines how to handle matching reservations
     #[arg(long, env, default_value_t = ReservationTimeMode::Exact)]
     reservation_time_mode: ReservationTimeMode,
+    /// Card to pay with: "default", the last 4 digits of a card or its Resy id (id:<id>
+    /// if the id is 4 digits long.)
+    #[arg(long, env = "RESY_PAYMENT", default_value = "default")]
+    payment: PaymentSelector,
+    /// Book slots that need to be paid for. Without this, or one of the fee limits below,
+    /// slots with a deposit or cancellation fee are skipped.
+    #[arg(long, env, action)]
+    allow_paid: bool,
+    /// Highest deposit per guest to accept, e.g. 25 or 25.50.
+    #[arg(long, env)]
+    max_deposit: Option<Money>,
+    /// Highest fee per guest for cancelling late to accept.
+    #[arg(long, env)]
+    max_cancellation_fee: Option<Money>,
+    /// Most slots to get the details of at the same time. The best slot whose details come
+    /// back is booked, the next ones are only booked if someone else got it first.
+    #[arg(long, env, default_value_t = 3)]
+    fan_out: usize,
+    /// Flag enabling json output of the booking confirmation instead of human-readable.
+    /// Only the confirmation goes to stdout, progress is always written to stderr.
+    #[arg(long, action)]
+    json: bool,
 
     #[command(subcommand)]
     command: Commands,
@@ -105,6 +160,30 @@ enum Commands {
         /// Time reservations become available.
         #[arg(short, long, env, default_value = "00:00")]
         start_time: String,
+        /// Milliseconds after start_time to make the first attempt, negative to start
+        /// early. E.g. 150 gives Resy a moment to release the slots.
+        #[arg(long, env, default_value_t = 0, allow_hyphen_values = true)]
+        lag: i64,
+        /// Number of requests used to measure Resy's clock, the first attempt is timed by
+        /// Resy's clock rather than the local one. 0 trusts the local clock.
+        #[arg(long, env, default_value_t = 5)]
+        clock_samples: u32,
+        /// Connections to keep open to Resy in the seconds before the drop. Requests made
+        /// at the same time each need their own unless Resy speaks HTTP/2.
+        #[arg(long, env, default_value_t = 4)]
+        connections: usize,
+        /// Guess the tokens of the slots before the drop from a day that can already be
+        /// booked, then ask for their details at the drop while searching for slots. This
+        /// saves a round trip when the guess is right.
+        #[arg(long, env, action)]
+        speculative: bool,
+        /// Day to guess the tokens from in YYYYMMDD format. Defaults to a week before the
+        /// reservation date, or today if that's already passed.
+        #[arg(long, env, requires = "speculative")]
+        template_date: Option<String>,
+        /// Most tokens to guess.
+        #[arg(long, env, default_value_t = 3, requires = "speculative")]
+        speculative_count: usize,
     },
 }
 
@@ -139,96 +218,673 @@ fn get_matching_reservation(
     time_mode: &ReservationTimeMode,
 ) -> Option<ReservationSlot> {
     match time_mode {
-        ReservationTimeMode::Exact => {
-            return reservations
-                .iter()
-                .find(|&reservation_slot| {
-                    reservation_slot.date.to_datetime().time() == *time
-                        && table_type_matches(&reservation_slot.config.slot_type, table_type)
-                })
-                .cloned();
+        ReservationTimeMode::Exact => reservations
+            .iter()
+            .find(|&reservation_slot| {
+                reservation_slot.date.to_datetime().time() == *time
+                    && table_type_matches(&reservation_slot.config.slot_type, table_type)
+            })
+            .cloned(),
+        ReservationTimeMode::Earlier => reservations
+            .iter()
+            .rfind(|&r| {
+                r.date.to_datetime().time() <= *time
+                    && table_type_matches(&r.config.slot_type, table_type)
+            })
+            .cloned(),
+        ReservationTimeMode::Later => reservations
+            .iter()
+            .find(|&r| {
+                r.date.to_datetime().time() >= *time
+                    && table_type_matches(&r.config.slot_type, table_type)
+            })
+            .cloned(),
+    }
+}
+
+/// Which paid slots can be booked. Limits are per guest like Resy shows them. A fee
+/// without a limit is refused unless paid slots are allowed. Service charges are added to
+/// the bill rather than charged when booking, so they're reported but never refused.
+#[derive(Debug, Clone, Default)]
+struct FeePolicy {
+    allow_paid: bool,
+    max_deposit: Option<Money>,
+    max_cancellation_fee: Option<Money>,
+}
+
+impl FeePolicy {
+    /// Checks one fee for the whole party against its limit, explaining why it's refused.
+    fn check_fee(
+        &self,
+        name: &str,
+        flag: &str,
+        fee: Money,
+        limit: Option<Money>,
+        party_size: u8,
+    ) -> Result<(), String> {
+        if fee.is_zero() {
+            return Ok(());
         }
-        ReservationTimeMode::Earlier => {
-            return reservations
-                .iter()
-                .filter(|&r| {
-                    r.date.to_datetime().time() <= *time
-                        && table_type_matches(&r.config.slot_type, table_type)
-                })
-                .last()
-                .cloned();
+        match limit {
+            None if self.allow_paid => Ok(()),
+            None => Err(format!(
+                "it has a {} of {} for {} guests, pass --allow-paid or {} to book it",
+                name, fee, party_size, flag
+            )),
+            Some(limit) => {
+                let party_limit = Money::from_cents(limit.cents() * i64::from(party_size));
+                if fee <= party_limit {
+                    return Ok(());
+                }
+                Err(format!(
+                    "its {} of {} is over the {} allowed by {} ({} per guest)",
+                    name, fee, party_limit, flag, limit
+                ))
+            }
         }
-        ReservationTimeMode::Later => {
-            return reservations
-                .iter()
-                .find(|&r| {
-                    r.date.to_datetime().time() >= *time
-                        && table_type_matches(&r.config.slot_type, table_type)
-                })
-                .cloned();
+    }
+
+    /// Checks fees for the whole party.
+    fn check_fees(
+        &self,
+        deposit: Money,
+        cancellation_fee: Money,
+        party_size: u8,
+    ) -> Result<(), String> {
+        self.check_fee(
+            "deposit",
+            "--max-deposit",
+            deposit,
+            self.max_deposit,
+            party_size,
+        )?;
+        self.check_fee(
+            "cancellation fee",
+            "--max-cancellation-fee",
+            cancellation_fee,
+            self.max_cancellation_fee,
+            party_size,
+        )
+    }
+
+    fn check_slot(&self, slot: &ReservationSlot, party_size: u8) -> Result<(), String> {
+        let deposit = slot.payment.deposit(party_size);
+        let cancellation_fee = slot.payment.cancellation_fee(party_size);
+        // Paid slots without a listed fee can't be checked against the limits
+        if slot.payment.is_paid
+            && deposit.is_zero()
+            && cancellation_fee.is_zero()
+            && !self.allow_paid
+        {
+            return Err("it has to be paid for, pass --allow-paid to book it".to_owned());
         }
+        self.check_fees(deposit, cancellation_fee, party_size)
+    }
+
+    /// The details quote the final amounts, which may differ from the slot's.
+    fn check_details(&self, details: &ReservationDetails, party_size: u8) -> Result<(), String> {
+        self.check_fees(details.deposit(), details.cancellation_fee(), party_size)
     }
 }
 
-async fn attempt_reservation(
-    resy_client: &ResyClient,
-    restaurant_id: &String,
-    date: &NaiveDate,
-    time: &NaiveTime,
+/// What to book, put together from the command line.
+struct BookingRequest {
+    restaurant_id: String,
+    date: NaiveDate,
+    time: NaiveTime,
     party_size: u8,
-    table_type: &Option<String>,
-    time_mode: &ReservationTimeMode,
-) -> anyhow::Result<()> {
+    table_type: Option<String>,
+    time_mode: ReservationTimeMode,
+    payment: PaymentSelector,
+    /// Card picked by `payment`, looked up ahead of time so booking doesn't wait on it.
+    payment_method: PaymentMethod,
+    fee_policy: FeePolicy,
+    /// Most details requests in flight at once, at least 1.
+    fan_out: usize,
+}
+
+/// How long each request of a booking attempt took, reported once the attempt is over.
+struct PhaseTimings {
+    started: Instant,
+    phases: Mutex<Vec<(String, Duration)>>,
+}
+
+impl PhaseTimings {
+    fn start() -> PhaseTimings {
+        PhaseTimings {
+            started: Instant::now(),
+            phases: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Runs one phase of the attempt, recording how long it took whether or not it worked.
+    async fn time<T>(&self, phase: String, future: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let output = future.await;
+        self.phases.lock().unwrap().push((phase, started.elapsed()));
+        output
+    }
+
+    /// E.g. "Attempt took 412ms: find 180ms, details 19:00 Inside 120ms, book 110ms".
+    /// Phases cut short because another one got there first aren't listed.
+    fn report(&self) -> String {
+        let phases: Vec<String> = self
+            .phases
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(phase, took)| format!("{} {}ms", phase, took.as_millis()))
+            .collect();
+        format!(
+            "Attempt took {}ms: {}",
+            self.started.elapsed().as_millis(),
+            phases.join(", ")
+        )
+    }
+}
+
+/// Why an attempt fails when every slot was skipped because of its fees.
+const NO_ACCEPTABLE_FEES: &str =
+    "No reservation with acceptable fees was found for the given time and time_mode";
+
+/// Puts the reservations matching the request in order of preference, best first.
+fn rank_reservations(
+    reservations: &[ReservationSlot],
+    request: &BookingRequest,
+) -> Vec<ReservationSlot> {
+    let mut candidates = reservations.to_vec();
+    let mut ranked = Vec::new();
+    while let Some(r) = get_matching_reservation(
+        &candidates,
+        &request.time,
+        &request.table_type,
+        &request.time_mode,
+    ) {
+        candidates.retain(|c| c.config.token != r.config.token);
+        ranked.push(r);
+    }
+    ranked
+}
+
+/// Describes a slot in messages, e.g. "19:00 Inside".
+fn describe_slot(time: NaiveTime, table_type: &str) -> String {
+    format!("{} {}", time.format("%H:%M"), table_type)
+}
+
+/// Finds the slots open for the request, best match first. Slots whose fees aren't
+/// acceptable are left out.
+async fn find_ranked_slots(
+    resy_client: &ResyClient,
+    request: &BookingRequest,
+    timings: &PhaseTimings,
+) -> anyhow::Result<Vec<ReservationSlot>> {
     // Using the resy_id, get the reservations available
-    let reservations = resy_client
-        .get_reservations(restaurant_id, date, party_size)
+    let reservations = timings
+        .time(
+            "find".to_owned(),
+            resy_client.get_reservations(&request.restaurant_id, &request.date, request.party_size),
+        )
         .await?;
     if reservations.is_empty() {
         return Err(anyhow!(
             "No reservations exist at the restaurant for the given date and party size"
         ));
     }
-    // Find a reservation that matches the time requested
-    let matching_reservation = get_matching_reservation(&reservations, time, table_type, time_mode);
-    match matching_reservation {
-        Some(r) => {
-            // Get the reservation details to book. For now, let's assume if we got a reservation slot
-            // that this function won't fail.
-            let reservation_details = resy_client
-                .get_reservation_details(&r, date, party_size)
-                .await?;
-            // This naively also assumes that the reservation will book properly for now.
-            let booking_res = resy_client
-                .book_restaurant(
-                    &reservation_details.book_token,
-                    &reservation_details.get_payment_id().unwrap(),
-                )
-                .await;
-            match booking_res {
-                Ok(b) => Ok(b),
-                Err(e) => Err(e),
+    let ranked = rank_reservations(&reservations, request);
+    if ranked.is_empty() {
+        return Err(anyhow!(
+            "No reservation was found for the given time and time_mode"
+        ));
+    }
+    let acceptable: Vec<ReservationSlot> = ranked
+        .into_iter()
+        .filter(
+            |r| match request.fee_policy.check_slot(r, request.party_size) {
+                Ok(()) => true,
+                Err(reason) => {
+                    eprintln!(
+                        "Skipping the {} reservation: {}",
+                        describe_slot(r.date.start.time(), &r.config.slot_type),
+                        reason
+                    );
+                    false
+                }
+            },
+        )
+        .collect();
+    if acceptable.is_empty() {
+        return Err(anyhow!(NO_ACCEPTABLE_FEES));
+    }
+    Ok(acceptable)
+}
+
+/// A slot to get the details of.
+struct SlotToBook {
+    /// The slot's config token, which tells slots apart.
+    token: String,
+    /// The slot as shown in messages, see [`describe_slot`].
+    description: String,
+    specs: BookingSpecs,
+}
+
+/// A slot ready to book.
+struct Candidate {
+    slot: SlotToBook,
+    details: ReservationDetails,
+}
+
+/// Gets a slot's details once one of the fan-out permits is free and checks the fees they
+/// quote, which may differ from the slot's.
+async fn acceptable_details(
+    request: &BookingRequest,
+    timings: &PhaseTimings,
+    permits: &Semaphore,
+    phase: String,
+    slot: SlotToBook,
+    details: impl Future<Output = Result<ReservationDetails, ResyError>>,
+) -> anyhow::Result<Candidate> {
+    let _permit = permits.acquire().await?;
+    let details = timings
+        .time(format!("{} {}", phase, slot.description), details)
+        .await?;
+    if let Err(reason) = request
+        .fee_policy
+        .check_details(&details, request.party_size)
+    {
+        eprintln!("Skipping the {} reservation: {}", slot.description, reason);
+        return Err(anyhow!(NO_ACCEPTABLE_FEES));
+    }
+    Ok(Candidate { slot, details })
+}
+
+/// A booking that failed in a way that leaves it unclear whether Resy took the table, so
+/// trying again could book a second one.
+#[derive(Debug)]
+struct BookingOutcomeUnknown {
+    slot: String,
+    source: ResyError,
+}
+
+impl BookingOutcomeUnknown {
+    /// Whether a failed booking may still have gone through: Resy errored out or the
+    /// connection dropped after the request was sent.
+    fn applies_to(error: &ResyError) -> bool {
+        match error {
+            ResyError::Network(e) => !e.is_connect(),
+            ResyError::Api { status, .. } => *status >= 500,
+            ResyError::Schema(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Display for BookingOutcomeUnknown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "booking the {} reservation failed but may have gone through, check your upcoming reservations before trying again: {}",
+            self.slot, self.source
+        )
+    }
+}
+
+impl std::error::Error for BookingOutcomeUnknown {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+async fn book_reservation(
+    resy_client: &ResyClient,
+    request: &BookingRequest,
+    candidate: &Candidate,
+    timings: &PhaseTimings,
+) -> Result<BookingConfirmation, ResyError> {
+    timings
+        .time(
+            "book".to_owned(),
+            resy_client.book_restaurant(
+                &candidate.details,
+                &candidate.slot.specs,
+                &request.payment_method,
+            ),
+        )
+        .await
+}
+
+/// Gets the details of the slots that best match the request, at most `fan_out` at a
+/// time, along with those of any guessed tokens. Slots are booked one at a time as their
+/// details come in, the best found slot first, until a booking goes through. A slot lost
+/// to someone else moves on to the next one, but any other booking error ends the attempt,
+/// with a [`BookingOutcomeUnknown`] if the booking may have gone through.
+async fn attempt_reservation(
+    resy_client: &ResyClient,
+    request: &BookingRequest,
+    tokens: &[RgsToken],
+    timings: &PhaseTimings,
+) -> anyhow::Result<BookingConfirmation> {
+    let permits = Semaphore::new(request.fan_out);
+    let permits = &permits;
+    let guessed = stream::iter(tokens)
+        .map(|token| {
+            acceptable_details(
+                request,
+                timings,
+                permits,
+                "guessed details".to_owned(),
+                SlotToBook {
+                    token: token.to_string(),
+                    description: describe_slot(token.time, &token.table_type),
+                    specs: BookingSpecs::from(token),
+                },
+                resy_client.get_reservation_details_for_token(token),
+            )
+        })
+        .buffer_unordered(request.fan_out);
+    let found = stream::once(find_ranked_slots(resy_client, request, timings))
+        .map(|ranked| match ranked {
+            Ok(slots) => stream::iter(slots)
+                .map(|slot| async move {
+                    let to_book = SlotToBook {
+                        token: slot.config.token.clone(),
+                        description: describe_slot(slot.date.start.time(), &slot.config.slot_type),
+                        specs: BookingSpecs::for_slot(&slot, request.party_size)?,
+                    };
+                    acceptable_details(
+                        request,
+                        timings,
+                        permits,
+                        "details".to_owned(),
+                        to_book,
+                        resy_client.get_reservation_details(
+                            &slot,
+                            &request.date,
+                            request.party_size,
+                        ),
+                    )
+                    .await
+                })
+                // Keeps the slots' order so the best one is booked first
+                .buffered(request.fan_out)
+                .left_stream(),
+            Err(e) => stream::once(future::ready(Err(e))).right_stream(),
+        })
+        .flatten();
+    let mut candidates = pin!(stream::select(guessed, found));
+
+    // A guessed token and the search can both come up with the same slot
+    let mut tried = HashSet::new();
+    let mut last_error = None;
+    while let Some(candidate) = candidates.next().await {
+        let candidate = match candidate {
+            Ok(candidate) => candidate,
+            Err(e) => {
+                last_error = Some(e);
+                continue;
             }
+        };
+        if !tried.insert(candidate.slot.token.clone()) {
+            continue;
         }
-        None => Err(anyhow!(
-            "No reservation was found for the given time and time_mode"
-        )),
+        let service_charge = candidate.details.service_charge();
+        if !service_charge.is_zero() {
+            eprintln!(
+                "The {} reservation adds a service charge of {} to the bill",
+                candidate.slot.description, service_charge
+            );
+        }
+        match book_reservation(resy_client, request, &candidate, timings).await {
+            Ok(confirmation) => return Ok(confirmation),
+            Err(e @ ResyError::BookingConflict(_)) => {
+                eprintln!("Lost the {} reservation: {}", candidate.slot.description, e);
+                last_error = Some(e.into());
+            }
+            Err(e) if BookingOutcomeUnknown::applies_to(&e) => {
+                return Err(BookingOutcomeUnknown {
+                    slot: candidate.slot.description,
+                    source: e,
+                }
+                .into())
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| anyhow!("No reservation was found for the given time and time_mode")))
+}
+
+/// Attempts the reservation up to `retry_count` times. Gives up early if a booking may
+/// have gone through, see [`BookingOutcomeUnknown`].
+async fn attempt_with_retries(
+    resy_client: &ResyClient,
+    request: &BookingRequest,
+    tokens: &[RgsToken],
+    retry_count: u8,
+    retry_delay: Duration,
+) -> anyhow::Result<BookingConfirmation> {
+    let mut last_error = anyhow!("No attempt to book a reservation was made");
+    for i in 0..retry_count {
+        eprintln!(
+            "On try {} out ouf {} to book a reservation.",
+            i + 1,
+            retry_count
+        );
+        let timings = PhaseTimings::start();
+        let reservation_attempt = attempt_reservation(resy_client, request, tokens, &timings).await;
+        eprintln!("{}", timings.report());
+        match reservation_attempt {
+            Ok(confirmation) => return Ok(confirmation),
+            Err(e) if e.is::<BookingOutcomeUnknown>() => return Err(e),
+            Err(e) => {
+                eprintln!(
+                    "Encountered error on this attempt: {}, retrying in {} seconds",
+                    e,
+                    retry_delay.as_secs()
+                );
+                if i < retry_count - 1 {
+                    async_std::task::sleep(retry_delay).await;
+                }
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Guesses the tokens of the slots the drop will release from the slots already bookable
+/// on another day, best match first. Slots whose fees are refused there are left out.
+async fn speculative_tokens(
+    resy_client: &ResyClient,
+    request: &BookingRequest,
+    template_date: &NaiveDate,
+    count: usize,
+) -> anyhow::Result<Vec<RgsToken>> {
+    let reservations = resy_client
+        .get_reservations(&request.restaurant_id, template_date, request.party_size)
+        .await?;
+    rank_reservations(&reservations, request)
+        .iter()
+        .filter(|r| request.fee_policy.check_slot(r, request.party_size).is_ok())
+        .take(count)
+        .map(|r| Ok(r.config.rgs_token()?.on_date(request.date)))
+        .collect()
+}
+
+fn booking_request(
+    cli: &Cli,
+    date: NaiveDate,
+    time: NaiveTime,
+    payment_method: PaymentMethod,
+) -> BookingRequest {
+    BookingRequest {
+        restaurant_id: cli.restaurant_id.clone(),
+        date,
+        time,
+        party_size: cli.party_size,
+        table_type: cli.table_type.clone(),
+        time_mode: cli.reservation_time_mode.clone(),
+        payment: cli.payment.clone(),
+        payment_method,
+        fee_policy: FeePolicy {
+            allow_paid: cli.allow_paid,
+            max_deposit: cli.max_deposit,
+            max_cancellation_fee: cli.max_cancellation_fee,
+        },
+        fan_out: cli.fan_out.max(1),
+    }
+}
+
+/// Next time the local clock reads `start_time`, tomorrow if it has already passed today.
+fn next_start(start_time: NaiveTime, now: NaiveDateTime) -> NaiveDateTime {
+    let start = now.date().and_time(start_time);
+    if start < now {
+        start + Days::new(1)
+    } else {
+        start
+    }
+}
+
+/// Measures how far off the local clock is from Resy's, trusting the local clock if that
+/// doesn't work out.
+async fn sync_clock(resy_client: &ResyClient, samples: u32) -> ClockOffset {
+    if samples == 0 {
+        return ClockOffset::default();
+    }
+    match resy_client.measure_clock_offset(samples).await {
+        Ok(clock) => {
+            eprintln!(
+                "Resy's clock is {}ms {} the local clock (±{}ms)",
+                clock.offset.num_milliseconds().abs(),
+                if clock.offset < TimeDelta::zero() {
+                    "behind"
+                } else {
+                    "ahead of"
+                },
+                clock.uncertainty.num_milliseconds()
+            );
+            clock
+        }
+        Err(e) => {
+            eprintln!(
+                "Unable to measure Resy's clock: {}, going by the local clock",
+                e
+            );
+            ClockOffset::default()
+        }
+    }
+}
+
+/// Sleeps until `target` on Resy's clock. Long waits stop short to measure the clock again
+/// since clocks drift apart over hours, returning the latest measurement.
+async fn wait_until(
+    resy_client: &ResyClient,
+    target: DateTime<Utc>,
+    mut clock: ClockOffset,
+    samples: u32,
+) -> ClockOffset {
+    let remaining = target - clock.server_now();
+    if samples > 0 && remaining > CLOCK_RESYNC_LEAD * 2 {
+        tokio::time::sleep((remaining - CLOCK_RESYNC_LEAD).to_std().unwrap_or_default()).await;
+        clock = sync_clock(resy_client, samples).await;
+    }
+    if let Ok(remaining) = (target - clock.server_now()).to_std() {
+        tokio::time::sleep(remaining).await;
     }
+    clock
+}
+
+/// Keeps connections to Resy open until `target` on Resy's clock and looks up the card to
+/// pay with again, leaving only finding, getting details and booking for the drop. A round
+/// still going at `target` is abandoned.
+async fn keep_warm(
+    resy_client: &ResyClient,
+    request: &mut BookingRequest,
+    target: DateTime<Utc>,
+    clock: &ClockOffset,
+    connections: usize,
+) {
+    eprintln!(
+        "Keeping {} connections to Resy open until the drop",
+        connections
+    );
+    loop {
+        let round = async {
+            let (warmed, payment_method) = tokio::join!(
+                resy_client.warm_up(connections),
+                resy_client.check_payment_method(&request.payment)
+            );
+            if let Err(e) = warmed {
+                eprintln!("Unable to connect to Resy ahead of the drop: {}", e);
+            }
+            match payment_method {
+                Ok(payment_method) => request.payment_method = payment_method,
+                Err(e) => eprintln!("Unable to look up the card to pay with again: {}", e),
+            }
+        };
+        // A slow round must not hold up the drop
+        let deadline = (target - clock.server_now()).to_std().unwrap_or_default();
+        if tokio::time::timeout(deadline, round).await.is_err() {
+            eprintln!("Resy is slow to answer, starting without waiting for it");
+            return;
+        }
+        let remaining = target - clock.server_now();
+        let wait = remaining.min(WARM_UP_INTERVAL);
+        tokio::time::sleep(wait.to_std().unwrap_or_default()).await;
+        if remaining <= WARM_UP_INTERVAL {
+            return;
+        }
+    }
+}
+
+/// Prints the booking confirmation so the reservation can be looked up or cancelled later.
+fn print_confirmation(confirmation: &BookingConfirmation, json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string(confirmation)?);
+    } else {
+        println!(
+            "Booked your reservation, you should be receiving a confirmation email from Resy!"
+        );
+        println!(
+            "Reservation ID: {}, Venue ID: {}, Date: {} {}, Party size: {}",
+            confirmation.reservation_id,
+            confirmation.venue_id,
+            confirmation.date,
+            confirmation.time,
+            confirmation.party_size
+        );
+        println!("Resy token: {}", confirmation.resy_token);
+    }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    let mut date = get_default_date(cli.date);
-
-    let builder = ResyClientBuilder::new(cli.api_key, cli.auth_token);
+    let mut date = get_default_date(cli.date.clone());
 
-    let mut resy_client = builder.build();
+    let flags = Profile {
+        api_key: cli.api_key.clone(),
+        auth_token: cli.auth_token.clone(),
+        email: cli.email.clone(),
+        password: env::var(&cli.password_env).ok(),
+        base_url: cli.base_url.clone(),
+        ..Profile::default()
+    };
+    let mut builder = profile::client_builder(flags, cli.profile.as_deref())?;
+    if cli.no_cache {
+        builder = builder.no_cache();
+    }
+    let mut resy_client = builder.build()?;
 
     resy_client.load_config().await?;
 
+    // Make sure there's a card to book with now rather than finding out at the drop
+    let payment_method = resy_client.check_payment_method(&cli.payment).await?;
+    eprintln!("Paying with {}", payment_method.describe());
+
     let requested_time = NaiveTime::parse_from_str(&cli.time, "%H:%M").unwrap();
-    println!("Checking for reservations on {:?}", date);
+    eprintln!("Checking for reservations on {:?}", date);
 
     match &cli.command {
         Commands::Automatic {
@@ -236,97 +892,121 @@ async fn main() -> anyhow::Result<()> {
             retry_delay,
             offset,
             start_time,
+            lag,
+            clock_samples,
+            connections,
+            speculative,
+            template_date,
+            speculative_count,
         } => {
-            println!("User requested automatic mode");
+            eprintln!("User requested automatic mode");
             if let Some(offset) = offset {
                 date = date.checked_add_days(Days::new(*offset as u64)).unwrap();
             }
-            let now_date = Local::now().date_naive();
-            let now = Local::now().time();
-            let start_time = NaiveTime::parse_from_str(start_time, "%H:%M")
-                .expect("Invalid start_time provided");
-            let delay = if start_time < now {
-                // Need to use NaiveDateTime instead of just NaiveTime to get real delay, assuming that
-                // the start time really means wait until the next day.
-                let start_date = now_date
-                    .checked_add_days(Days::new(1))
-                    .unwrap()
-                    .and_time(start_time);
-                start_date - Local::now().naive_local()
-            } else {
-                start_time - now
-            };
-            println!("Waiting {} to start", delay.num_seconds());
-            if delay.num_seconds() > 0 {
-                async_std::task::sleep(Duration::from_secs(delay.num_seconds() as u64)).await;
-            }
-            for i in 0..*retry_count {
-                println!(
-                    "On try {} out ouf {} to book a reservation.",
-                    i + 1,
-                    retry_count
-                );
-                let reservation_attempt = attempt_reservation(
+            let mut request = booking_request(&cli, date, requested_time, payment_method);
+            // Guess the tokens now, the slots they're guessed from are already out
+            let mut tokens = Vec::new();
+            if *speculative {
+                let template_date = match template_date {
+                    Some(d) => get_default_date(Some(d.clone())),
+                    None => (date - Days::new(7)).max(Local::now().date_naive()),
+                };
+                match speculative_tokens(
                     &resy_client,
-                    &cli.restaurant_id,
-                    &date,
-                    &requested_time,
-                    cli.party_size,
-                    &cli.table_type,
-                    &cli.reservation_time_mode,
+                    &request,
+                    &template_date,
+                    *speculative_count,
                 )
-                .await;
-                match reservation_attempt {
-                    Ok(_) => {
-                        println!("Booked your reservation, you should be receiving a confirmation email from Resy!");
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        println!(
-                            "Encountered error on this attempt: {}, retrying in {} seconds",
-                            e, retry_delay
+                .await
+                {
+                    Ok(guessed) if !guessed.is_empty() => {
+                        eprintln!(
+                            "Guessed {} tokens from the slots on {}",
+                            guessed.len(),
+                            template_date
                         );
-                        if i < *retry_count - 1 {
-                            async_std::task::sleep(Duration::from_secs(*retry_delay as u64)).await;
-                        }
+                        tokens = guessed;
                     }
+                    Ok(_) => eprintln!(
+                        "No matching slots on {} to guess tokens from, searching at the drop instead",
+                        template_date
+                    ),
+                    Err(e) => eprintln!(
+                        "Unable to guess tokens from the slots on {}: {}, searching at the drop instead",
+                        template_date, e
+                    ),
                 }
             }
-        }
-        Commands::OneShot => {
-            println!("User requested one-shot mode");
-            attempt_reservation(
+            let start_time = NaiveTime::parse_from_str(start_time, "%H:%M")
+                .expect("Invalid start_time provided");
+            let target = next_start(start_time, Local::now().naive_local())
+                .and_local_timezone(Local)
+                .earliest()
+                .expect("start_time doesn't exist on the local clock today")
+                .to_utc()
+                + TimeDelta::milliseconds(*lag);
+            let clock = sync_clock(&resy_client, *clock_samples).await;
+            eprintln!(
+                "Waiting {:.3}s to start",
+                (target - clock.server_now()).num_milliseconds() as f64 / 1000.0
+            );
+            let clock =
+                wait_until(&resy_client, target - WARM_UP_LEAD, clock, *clock_samples).await;
+            keep_warm(&resy_client, &mut request, target, &clock, *connections).await;
+            let error = clock.server_now() - target;
+            eprintln!(
+                "Starting {}ms {} the target time by Resy's clock (±{}ms)",
+                error.num_milliseconds().abs(),
+                if error < TimeDelta::zero() {
+                    "before"
+                } else {
+                    "after"
+                },
+                clock.uncertainty.num_milliseconds()
+            );
+            let confirmation = attempt_with_retries(
                 &resy_client,
-                &cli.restaurant_id,
-                &date,
-                &requested_time,
-                cli.party_size,
-                &cli.table_type,
-                &cli.reservation_time_mode,
+                &request,
+                &tokens,
+                *retry_count,
+                Duration::from_secs(*retry_delay as u64),
             )
             .await?;
+            print_confirmation(&confirmation, cli.json)?;
+        }
+        Commands::OneShot => {
+            eprintln!("User requested one-shot mode");
+            let request = booking_request(&cli, date, requested_time, payment_method);
+            let timings = PhaseTimings::start();
+            let reservation_attempt =
+                attempt_reservation(&resy_client, &request, &[], &timings).await;
+            eprintln!("{}", timings.report());
+            let confirmation = reservation_attempt?;
+            print_confirmation(&confirmation, cli.json)?;
         }
     }
 
+    if let Err(e) = resy_client.finish_config_refresh().await {
+        eprintln!("Unable to refresh the cached city configs: {}", e);
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{env, fs::File, io::BufReader};
-
+    use chrono::Weekday;
+    use libresy::ResyClientBuilder;
+    use resy_sim::scenario::Scenario;
     use serde_json::Value;
 
     use super::*;
 
+    /// Find response shared with libresy's tests.
+    const TEST_FIND: &str = include_str!("../../libresy/src/test_data/test_find.json");
+
     #[test]
     fn test_reservation_preferences() {
-        let cache_dir = env::current_dir().unwrap().join("src").join("test_data");
-
-        let test_data_path = cache_dir.join("test_find.json");
-        let reader =
-            BufReader::new(File::open(test_data_path).expect("Unable to open test data file"));
-        let data: Value = serde_json::from_reader(reader).expect("Unable to parse file");
+        let data: Value = serde_json::from_str(TEST_FIND).expect("Unable to parse file");
         let reservations: Vec<ReservationSlot> =
             serde_json::from_value(data["results"]["venues"][0]["slots"].clone()).unwrap();
 
@@ -395,4 +1075,152 @@ mod tests {
             assert_eq!(slot.date.to_datetime().time(), expected_time);
         }
     }
+
+    #[test]
+    fn test_fee_policy() {
+        let data: Value = serde_json::from_str(TEST_FIND).unwrap();
+        let free: ReservationSlot =
+            serde_json::from_value(data["results"]["venues"][0]["slots"][0].clone()).unwrap();
+        let mut paid = free.clone();
+        paid.payment.deposit_fee = Some(Money::from_cents(10000));
+
+        let policy = FeePolicy::default();
+        assert!(policy.check_slot(&free, 2).is_ok());
+        let reason = policy.check_slot(&paid, 2).unwrap_err();
+        assert!(reason.contains("--allow-paid"), "{}", reason);
+
+        let capped = FeePolicy {
+            max_deposit: Some("50".parse().unwrap()),
+            ..FeePolicy::default()
+        };
+        assert!(capped.check_slot(&paid, 2).is_err());
+        paid.payment.deposit_fee = Some(Money::from_cents(5000));
+        assert!(capped.check_slot(&paid, 2).is_ok());
+        // Only the deposit is capped, a cancellation fee still needs to be allowed
+        paid.payment.cancellation_fee = Some(Money::from_cents(2500));
+        assert!(capped.check_slot(&paid, 2).is_err());
+
+        let allowed = FeePolicy {
+            allow_paid: true,
+            ..capped
+        };
+        assert!(allowed.check_slot(&paid, 2).is_ok());
+    }
+
+    /// Client for a fresh sim serving the scenario.
+    async fn sim_client(scenario: Scenario) -> ResyClient {
+        let addr = resy_sim::spawn("127.0.0.1:0".parse().unwrap(), scenario)
+            .await
+            .unwrap();
+        ResyClientBuilder::new("key".to_owned(), "token".to_owned())
+            .base_url(&format!("http://{}", addr))
+            .build()
+            .unwrap()
+    }
+
+    /// Request for a table for 2 at the sim's venue a week after next Tuesday, at `time` or
+    /// later, paid with the default card.
+    async fn sim_request(
+        resy_client: &ResyClient,
+        time: NaiveTime,
+        fan_out: usize,
+    ) -> BookingRequest {
+        BookingRequest {
+            restaurant_id: "65615".to_owned(),
+            date: resy_sim::next_weekday(Weekday::Tue) + Days::new(7),
+            time,
+            party_size: 2,
+            table_type: None,
+            time_mode: ReservationTimeMode::Later,
+            payment: PaymentSelector::Default,
+            payment_method: resy_client
+                .check_payment_method(&PaymentSelector::Default)
+                .await
+                .unwrap(),
+            fee_policy: FeePolicy::default(),
+            fan_out,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_speculative_reservation() {
+        // The search never answers so only the guessed tokens can book
+        let mut scenario = Scenario::default();
+        scenario.venues[0].find_hangs = true;
+        let resy_client = sim_client(scenario).await;
+        let request =
+            sim_request(&resy_client, NaiveTime::from_hms_opt(12, 40, 0).unwrap(), 3).await;
+
+        // Tokens are guessed from slots found on another server, before the drop
+        let template_client = sim_client(Scenario::default()).await;
+        let template_date = resy_sim::next_weekday(Weekday::Tue);
+        let mut tokens = speculative_tokens(&template_client, &request, &template_date, 2)
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 2);
+        let start = |h, m| request.date.and_hms_opt(h, m, 0).unwrap();
+        assert_eq!(tokens[0].start(), start(12, 45));
+        assert_eq!(tokens[1].start(), start(13, 0));
+
+        // A wrong guess doesn't get in the way of a right one
+        tokens[0].table_type = "Patio".to_owned();
+        let timings = PhaseTimings::start();
+        let confirmation = attempt_reservation(&resy_client, &request, &tokens, &timings)
+            .await
+            .unwrap();
+        // The search never finished so it isn't reported
+        let report = timings.report();
+        assert!(
+            report.contains("guessed details 13:00 Inside"),
+            "{}",
+            report
+        );
+        assert!(!report.contains("find"), "{}", report);
+        assert_eq!(confirmation.date, request.date);
+        assert_eq!(confirmation.time.to_string(), "13:00:00");
+    }
+
+    #[tokio::test]
+    async fn test_parallel_reservation() {
+        // The best slot is taken by the time it's booked
+        let mut scenario = Scenario::default();
+        scenario.venues[0].book_failures = 1;
+        let resy_client = sim_client(scenario).await;
+        let request =
+            sim_request(&resy_client, NaiveTime::from_hms_opt(12, 30, 0).unwrap(), 2).await;
+
+        let timings = PhaseTimings::start();
+        let confirmation = attempt_reservation(&resy_client, &request, &[], &timings)
+            .await
+            .unwrap();
+        assert_eq!(confirmation.time.to_string(), "12:45:00");
+        let report = timings.report();
+        assert!(report.contains("details 12:30 Inside"), "{}", report);
+        assert!(report.contains("details 12:45 Inside"), "{}", report);
+
+        // Only the slot that went through was booked
+        let booked = resy_client.get_upcoming_reservations().await.unwrap();
+        assert_eq!(booked.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_booking_outcome_unknown() {
+        // Resy takes the table but errors out while answering
+        let mut scenario = Scenario::default();
+        scenario.venues[0].book_failures = 1;
+        scenario.venues[0].book_failure_status = 500;
+        scenario.venues[0].book_failures_commit = true;
+        let resy_client = sim_client(scenario).await;
+        let request =
+            sim_request(&resy_client, NaiveTime::from_hms_opt(12, 30, 0).unwrap(), 2).await;
+
+        let error = attempt_with_retries(&resy_client, &request, &[], 3, Duration::ZERO)
+            .await
+            .unwrap_err();
+        assert!(error.is::<BookingOutcomeUnknown>(), "{}", error);
+
+        // Neither the next slot nor another attempt was booked
+        let booked = resy_client.get_upcoming_reservations().await.unwrap();
+        assert_eq!(booked.len(), 1);
+    }
 }
@@ -1,33 +1,127 @@
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    time::Duration,
+};
+
 use anyhow::anyhow;
-use chrono::NaiveDate;
-use clap::{CommandFactory, Parser};
-use libresy::ResyClientBuilder;
+use chrono::{Datelike, Months, NaiveDate, NaiveTime, TimeDelta, Weekday};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use libresy::{
+    cache::{ConfigCache, DEFAULT_CACHE_TTL},
+    profile,
+    resy_data::{
+        AvailabilityCalendar, DayStatus, DiscoverQuery, GeoFilter, ReservationSlot,
+        RestaurantCityConfig, RestaurantSearchResult, Venue, DEFAULT_DISCOVER_LIMIT,
+    },
+    Profile, Profiles, ResyClient, ResyError,
+};
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
     #[arg(long, env, default_value = "US")]
     country: String,
-    #[arg(short, long, env)]
-    city: String,
+    #[arg(short, long, env, required = true)]
+    city: Option<String>,
     #[arg(trailing_var_arg = true)]
     restaurant_names: Vec<String>,
     #[command(flatten)]
     verbose: clap_verbosity_flag::Verbosity,
+    /// Name of a profile in profiles.toml to take credentials from. Credentials passed as
+    /// flags or env vars take priority over the profile's.
+    #[arg(long, env = "RESY_PROFILE")]
+    profile: Option<String>,
     #[arg(short = 'k', long, env, hide_env_values = true)]
-    api_key: String,
+    api_key: Option<String>,
     #[arg(short, long, env, hide_env_values = true)]
-    auth_token: String,
+    auth_token: Option<String>,
+    /// Email to log in with instead of providing an auth token. The token is refreshed
+    /// automatically when it expires.
+    #[arg(long, env = "RESY_EMAIL")]
+    email: Option<String>,
+    /// Name of the environment variable holding the password for --email.
+    #[arg(long, default_value = "RESY_PASSWORD")]
+    password_env: String,
+    /// Overrides the Resy API base URL, e.g. to point at a local stand-in server.
+    #[arg(long, env = "RESY_BASE_URL")]
+    base_url: Option<String>,
     #[arg(long, env, action)]
     no_cache: bool,
+    /// Days the cached cities are used for before they're refreshed.
+    #[arg(long, env, default_value_t = DEFAULT_CACHE_TTL.as_secs() / SECONDS_PER_DAY)]
+    cache_ttl_days: u64,
     #[arg(long, env, action)]
     /// If enabled, will display info about the restaurant then exit.
     info_only: bool,
+    /// Only accept restaurants with exactly the name searched for (ignoring case and
+    /// punctuation.)
+    #[arg(long, env, action)]
+    strict: bool,
     /// Size of party to find tables for.
     #[arg(short, long, env, default_value_t = 2)]
     party_size: u8,
     #[arg(short, long, env)]
     date: Option<String>,
+    /// Show which days have tables from this date (YYYYMMDD) instead of listing the
+    /// slots on one day. Defaults to today when only --to is given.
+    #[arg(long, conflicts_with_all = ["date", "info_only"])]
+    from: Option<String>,
+    /// Last day (YYYYMMDD) to show with --from, defaults to the end of the month.
+    #[arg(long, conflicts_with_all = ["date", "info_only"])]
+    to: Option<String>,
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// List every city Resy supports, these are the valid values for --city.
+    Cities,
+    /// List restaurants around the city with open tables for --party-size on --date.
+    Discover(DiscoverArgs),
+    /// Manage the cache of Resy cities used to search in.
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Args)]
+struct DiscoverArgs {
+    /// Only show tables around this time (HH:MM).
+    #[arg(long)]
+    time: Option<NaiveTime>,
+    /// Minutes before or after --time tables are still shown for. The window stops at
+    /// midnight on either side of the day searched.
+    #[arg(long, default_value_t = 30, requires = "time")]
+    flex: u32,
+    /// Search around this point instead of the city's center, given as LAT,LON.
+    #[arg(long, allow_hyphen_values = true)]
+    near: Option<String>,
+    /// How far from the center to search, in meters.
+    #[arg(long, default_value_t = 5000)]
+    radius: u16,
+    /// Max number of restaurants to show. Fewer are shown only when there aren't enough
+    /// with tables in the time window.
+    #[arg(long, default_value_t = DEFAULT_DISCOVER_LIMIT)]
+    limit: u16,
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Show where the cache is stored, how old it is and its size.
+    Show,
+    /// Delete the cache, the next search fetches the cities from Resy again.
+    Clear,
+    /// Fetch the cities from Resy and rewrite the cache.
+    Refresh,
 }
 
 /// Normalizes the date from YYYYMMDD to YYYY-MM-DD for Resy requests. Will use
@@ -44,10 +138,363 @@ fn get_default_date(provided_date: Option<String>) -> NaiveDate {
     }
 }
 
+/// Range of days to show a calendar for, if --from or --to was given.
+fn get_calendar_range(cli: &Cli) -> anyhow::Result<Option<(NaiveDate, NaiveDate)>> {
+    if cli.from.is_none() && cli.to.is_none() {
+        return Ok(None);
+    }
+    let from = get_default_date(cli.from.clone());
+    let to = match &cli.to {
+        Some(_) => get_default_date(cli.to.clone()),
+        None => from
+            .with_day(1)
+            .and_then(|first| first.checked_add_months(Months::new(1)))
+            .and_then(|next| next.pred_opt())
+            .unwrap_or(from),
+    };
+    if to < from {
+        return Err(anyhow!("--to must not be before --from"));
+    }
+    Ok(Some((from, to)))
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+fn cache_ttl(cli: &Cli) -> Duration {
+    Duration::from_secs(cli.cache_ttl_days * SECONDS_PER_DAY)
+}
+
+fn build_client(cli: &Cli) -> anyhow::Result<ResyClient> {
+    let flags = Profile {
+        api_key: cli.api_key.clone(),
+        auth_token: cli.auth_token.clone(),
+        email: cli.email.clone(),
+        password: env::var(&cli.password_env).ok(),
+        base_url: cli.base_url.clone(),
+        ..Profile::default()
+    };
+    let mut builder =
+        profile::client_builder(flags, cli.profile.as_deref())?.cache_ttl(cache_ttl(cli));
+    if cli.no_cache {
+        builder = builder.no_cache();
+    }
+    if cli.strict {
+        builder = builder.strict_match();
+    }
+    Ok(builder.build()?)
+}
+
+/// Formats how long ago something happened using its largest unit.
+fn format_age(age: chrono::Duration) -> String {
+    if age.num_days() > 0 {
+        format!("{} days", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{} hours", age.num_hours())
+    } else {
+        format!("{} minutes", age.num_minutes())
+    }
+}
+
+async fn run_cache_command(cli: &Cli, action: &CacheAction) -> anyhow::Result<()> {
+    let base_url = match (&cli.base_url, &cli.profile) {
+        (Some(base_url), _) => Some(base_url.clone()),
+        (None, Some(name)) => Profiles::load()?.get(name)?.base_url,
+        (None, None) => None,
+    };
+    let cache = match base_url {
+        Some(base_url) => ConfigCache::for_base_url(&base_url).ok_or_else(|| {
+            anyhow!(
+                "Cities aren't cached when using {}, only for Resy's API",
+                base_url
+            )
+        })?,
+        None => ConfigCache::user_cache()
+            .ok_or_else(|| anyhow!("Unable to find the cache directory"))?,
+    };
+    match action {
+        CacheAction::Show => {
+            println!("Cache location: {}", cache.path().display());
+            let Some(info) = cache.info()? else {
+                println!("Nothing has been cached yet");
+                return Ok(());
+            };
+            println!("Size: {} bytes", info.size);
+            match info.metadata {
+                Some(metadata) => {
+                    println!(
+                        "Fetched: {} ({} ago)",
+                        metadata.fetched_at.to_rfc3339(),
+                        format_age(metadata.age())
+                    );
+                    if let Some(etag) = &metadata.etag {
+                        println!("ETag: {}", etag);
+                    }
+                    if metadata.is_stale(cache_ttl(cli)) {
+                        println!("The cache is stale and will be refreshed on the next search");
+                    }
+                }
+                None => {
+                    println!("Fetched: unknown, the cache will be refreshed on the next search")
+                }
+            }
+        }
+        CacheAction::Clear => {
+            cache.clear()?;
+            println!("Cleared the cache at {}", cache.path().display());
+        }
+        CacheAction::Refresh => {
+            let mut resy_client = build_client(cli)?;
+            resy_client.refresh_config_cache().await?;
+            println!("Refreshed the cache at {}", cache.path().display());
+        }
+    }
+    Ok(())
+}
+
+/// Max number of restaurants offered when the name matches several of them.
+const MAX_CHOICES: usize = 10;
+
+/// Picks the restaurant to look up. When several restaurants have the name searched for,
+/// the user chooses between them.
+async fn choose_restaurant(
+    resy_client: &ResyClient,
+    city_config: &RestaurantCityConfig,
+    name: &str,
+) -> anyhow::Result<Option<RestaurantSearchResult>> {
+    let mut candidates = match resy_client.find_restaurant_by_name(city_config, name).await {
+        Err(ResyError::AmbiguousRestaurant { candidates, .. }) if io::stdin().is_terminal() => {
+            candidates
+        }
+        result => return Ok(result?),
+    };
+
+    candidates.truncate(MAX_CHOICES);
+    println!("Several restaurants match {}:", name);
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!("  {}) {}", i + 1, candidate.describe());
+    }
+    loop {
+        print!("Choose a restaurant [1-{}]: ", candidates.len());
+        io::stdout().flush()?;
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Err(anyhow!("No restaurant was chosen"));
+        }
+        match input.trim().parse::<usize>() {
+            Ok(choice) if (1..=candidates.len()).contains(&choice) => {
+                return Ok(Some(candidates.swap_remove(choice - 1)));
+            }
+            _ => println!("Enter a number between 1 and {}", candidates.len()),
+        }
+    }
+}
+
+/// Prints what's known about a venue so restaurants with similar names can be told apart.
+fn print_venue(venue: &Venue) {
+    println!("{} (Resy ID = {})", venue.name, venue.id.resy);
+    let mut summary = vec![venue.cuisine.clone(), "$".repeat(venue.price_range.into())];
+    if let Some(rating) = venue.rating {
+        summary.push(format!("rated {:.1}", rating));
+    }
+    println!("  {}", summary.join(", "));
+    let location: Vec<&str> = [&venue.location.neighborhood, &venue.location.name]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if !location.is_empty() {
+        println!("  {}", location.join(", "));
+    }
+    if let Some(group) = venue.venue_group.as_ref().filter(|g| g.venues.len() > 1) {
+        println!("  Part of {}", group.name);
+    }
+}
+
+fn print_slot(slot: &ReservationSlot, party_size: u8) {
+    let mut details = vec![format!("{} left", slot.quantity)];
+    if let Some(size) = &slot.size {
+        details.push(format!("parties of {}-{}", size.min, size.max));
+    }
+    if let Some(score) = slot.score() {
+        details.push(format!("score {:.1}", score));
+    }
+    let deposit = slot.payment.deposit(party_size);
+    if !deposit.is_zero() {
+        details.push(format!("{} deposit", deposit));
+    }
+    let cancellation_fee = slot.payment.cancellation_fee(party_size);
+    if !cancellation_fee.is_zero() {
+        details.push(format!("{} cancellation fee", cancellation_fee));
+    }
+    let time = match slot.date.end {
+        Some(end) => format!(
+            "{}-{}",
+            slot.date.start.format("%H:%M"),
+            end.format("%H:%M")
+        ),
+        None => slot.date.start.format("%H:%M").to_string(),
+    };
+    println!(
+        "  {} {} ({})",
+        time,
+        slot.config.slot_type,
+        details.join(", ")
+    );
+}
+
+/// Marks a day of the calendar grid with its status. Days past the end of the calendar
+/// are unknown rather than closed.
+fn day_marker(calendar: &AvailabilityCalendar, day: NaiveDate) -> char {
+    match calendar.status_on(day) {
+        DayStatus::Available => '+',
+        DayStatus::SoldOut => 'x',
+        DayStatus::Closed => '-',
+        DayStatus::Unknown => '?',
+    }
+}
+
+/// Prints a grid for every month in the range, marking each day with whether it has
+/// tables left.
+fn print_calendar(calendar: &AvailabilityCalendar, from: NaiveDate, to: NaiveDate) {
+    let header: Vec<String> = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"]
+        .iter()
+        .map(|day| format!("{:>3} ", day))
+        .collect();
+    let mut month = from.with_day(1).unwrap_or(from);
+    while month <= to {
+        println!();
+        println!("{}", month.format("%B %Y"));
+        println!("{}", header.join(" ").trim_end());
+        let mut week = vec!["    ".to_owned(); month.weekday().num_days_from_monday() as usize];
+        for day in month.iter_days().take_while(|d| d.month() == month.month()) {
+            let marker = if day < from || day > to {
+                ' '
+            } else {
+                day_marker(calendar, day)
+            };
+            week.push(format!("{:>3}{}", day.day(), marker));
+            if day.weekday() == Weekday::Sun {
+                println!("{}", week.join(" ").trim_end());
+                week.clear();
+            }
+        }
+        if !week.is_empty() {
+            println!("{}", week.join(" ").trim_end());
+        }
+        let Some(next) = month.checked_add_months(Months::new(1)) else {
+            break;
+        };
+        month = next;
+    }
+    println!();
+    println!("+ available, x sold out, - closed, ? unknown");
+}
+
+/// Parses a LAT,LON pair.
+fn parse_coordinates(near: &str) -> anyhow::Result<(f32, f32)> {
+    let (latitude, longitude) = near
+        .split_once(',')
+        .ok_or_else(|| anyhow!("--near must be given as LAT,LON"))?;
+    Ok((latitude.trim().parse()?, longitude.trim().parse()?))
+}
+
+async fn discover(cli: &Cli, args: &DiscoverArgs) -> anyhow::Result<()> {
+    let city = cli
+        .city
+        .as_ref()
+        .ok_or_else(|| anyhow!("You must provide a city to search in with --city"))?;
+    let date = get_default_date(cli.date.clone());
+
+    let mut resy_client = build_client(cli)?;
+    resy_client.load_config().await?;
+    let city_config = resy_client.get_restaurant_city_config(city, &cli.country)?;
+
+    let geo = match &args.near {
+        Some(near) => {
+            let (latitude, longitude) = parse_coordinates(near)?;
+            GeoFilter::new(latitude, longitude, args.radius)
+        }
+        None => GeoFilter::around(&city_config, args.radius),
+    };
+    let mut query = DiscoverQuery::new(geo, date, cli.party_size).limit(args.limit);
+    if let Some(time) = args.time {
+        let (earliest, latest) = time_window(time, args.flex);
+        query = query.time_window(earliest, latest);
+    }
+
+    let venues = resy_client.discover(&query).await;
+    finish_config_refresh(&mut resy_client).await;
+    let venues = venues?;
+    if venues.is_empty() {
+        println!(
+            "No restaurants have tables on {} for a party size of {}",
+            date, cli.party_size
+        );
+        return Ok(());
+    }
+    for venue in venues.iter() {
+        println!("{}", venue.restaurant.describe());
+        for slot in venue.slots.iter() {
+            print_slot(slot, cli.party_size);
+        }
+    }
+    Ok(())
+}
+
+/// Window of `flex` minutes either side of `time`. It's cut off at the start and end of the
+/// day searched rather than wrapping around midnight onto the same day's other end.
+fn time_window(time: NaiveTime, flex: u32) -> (NaiveTime, NaiveTime) {
+    let flex = TimeDelta::minutes(flex.into());
+    let earliest = match time.overflowing_sub_signed(flex) {
+        (earliest, 0) => earliest,
+        _ => NaiveTime::from_hms_opt(0, 0, 0).unwrap(),
+    };
+    let latest = match time.overflowing_add_signed(flex) {
+        (latest, 0) => latest,
+        _ => NaiveTime::from_hms_nano_opt(23, 59, 59, 999_999_999).unwrap(),
+    };
+    (earliest, latest)
+}
+
+/// Waits for the refresh of a stale city cache started when loading the cities, so it
+/// isn't cut short when the searcher exits.
+async fn finish_config_refresh(resy_client: &mut ResyClient) {
+    if let Err(e) = resy_client.finish_config_refresh().await {
+        eprintln!("Unable to refresh the cached cities: {}", e);
+    }
+}
+
+async fn list_cities(cli: &Cli) -> anyhow::Result<()> {
+    let mut resy_client = build_client(cli)?;
+    resy_client.load_config().await?;
+    finish_config_refresh(&mut resy_client).await;
+    let mut cities = resy_client.cities().to_vec();
+    cities.sort_by(|a, b| {
+        a.country_code
+            .cmp(&b.country_code)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    for city in cities.iter() {
+        println!(
+            "{} ({}), {}, time zone {}",
+            city.name, city.url_slug, city.country_code, city.time_zone
+        );
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
+    match &cli.command {
+        Some(Commands::Cities) => return list_cities(&cli).await,
+        Some(Commands::Discover(args)) => return discover(&cli, args).await,
+        Some(Commands::Cache { action }) => return run_cache_command(&cli, action).await,
+        None => {}
+    }
+    let city = cli.city.clone().unwrap_or_default();
+
     // Since restaurant name can be multiple positional args, check that the user
     // actually provided them
     if cli.restaurant_names.is_empty() {
@@ -55,50 +502,65 @@ async fn main() -> anyhow::Result<()> {
         return Err(anyhow!("You must provide a restaurant name to search for!"));
     }
 
-    let date = get_default_date(cli.date);
-
-    let mut builder = ResyClientBuilder::new(cli.api_key, cli.auth_token);
-    if cli.no_cache {
-        builder = builder.no_cache();
-    }
+    let date = get_default_date(cli.date.clone());
+    let calendar_range = get_calendar_range(&cli)?;
 
-    let mut resy_client = builder.build();
+    let mut resy_client = build_client(&cli)?;
 
     resy_client.load_config().await?;
+    let result = search(&cli, &resy_client, &city, date, calendar_range).await;
+    finish_config_refresh(&mut resy_client).await;
+    result
+}
 
+/// Looks for the restaurant named on the command line and prints its open slots.
+async fn search(
+    cli: &Cli,
+    resy_client: &ResyClient,
+    city: &str,
+    date: NaiveDate,
+    calendar_range: Option<(NaiveDate, NaiveDate)>,
+) -> anyhow::Result<()> {
     let restaurant_name = cli.restaurant_names.join(" ");
 
+    let dates = match calendar_range {
+        Some((from, to)) => format!("from {} to {}", from, to),
+        None => format!("on {}", date),
+    };
     println!(
-        "Looking for reservations at {} on {} for a party size of {}",
-        restaurant_name, date, cli.party_size
+        "Looking for reservations at {} {} for a party size of {}",
+        restaurant_name, dates, cli.party_size
     );
 
     // Try and find a matching restaurant config for the city/country/restaurant_name
-    let city_config = resy_client
-        .get_restaurant_city_config(&cli.city, &cli.country)
-        .unwrap_or_else(|| panic!("No city {} was found in country {}", cli.city, cli.country));
+    let city_config = resy_client.get_restaurant_city_config(city, &cli.country)?;
 
     // After we have the city, lets try to find the restaurant
-    let restaurant = resy_client
-        .find_restaurant_by_name(&city_config, &restaurant_name)
-        .await?;
+    let restaurant = choose_restaurant(resy_client, &city_config, &restaurant_name).await?;
     match restaurant {
         Some(r) => {
             if cli.info_only {
-                println!("Restaurant Name: {}, Resy ID: {}", r.name, r.object_id);
+                print_venue(&resy_client.get_venue(&r.object_id).await?);
+                return Ok(());
+            }
+            if let Some((from, to)) = calendar_range {
+                let calendar = resy_client
+                    .get_availability_calendar(&r.object_id, cli.party_size, &from, &to)
+                    .await?;
+                print_venue(&resy_client.get_venue(&r.object_id).await?);
+                print_calendar(&calendar, from, to);
                 return Ok(());
             }
-            let reservations = resy_client
-                .get_reservations(&r.object_id, &date, cli.party_size)
+            let availability = resy_client
+                .get_venue_availability(&r.object_id, &date, cli.party_size)
                 .await?;
+            print_venue(&availability.venue);
+            let reservations = availability.slots;
             if !reservations.is_empty() {
-                println!(
-                    "Found the following reservations at {} (Resy ID = {})",
-                    r.name, r.object_id
-                );
+                println!("Found the following reservations:");
                 // Print the reservations
                 for reservation in reservations.iter() {
-                    println!("{:?}", reservation);
+                    print_slot(reservation, cli.party_size);
                 }
             } else {
                 println!(
@@ -110,9 +572,61 @@ async fn main() -> anyhow::Result<()> {
         None => {
             return Err(anyhow!(format!(
                 "Unable to find a restaurant {} in {}",
-                restaurant_name, cli.city
+                restaurant_name, city
             )))
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_day_marker() {
+        let calendar: AvailabilityCalendar = serde_json::from_str(
+            r#"{
+                "last_calendar_day": "2024-11-02",
+                "scheduled": [
+                    {"date": "2024-11-01", "inventory": {"reservation": "available"}},
+                    {"date": "2024-11-02", "inventory": {"reservation": "closed"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        // The range asked for runs past the venue's booking horizon
+        let markers: String = NaiveDate::from_ymd_opt(2024, 11, 1)
+            .unwrap()
+            .iter_days()
+            .take(4)
+            .map(|day| day_marker(&calendar, day))
+            .collect();
+        assert_eq!(markers, "+-??");
+    }
+
+    #[test]
+    fn test_time_window() {
+        let time = |t| NaiveTime::parse_from_str(t, "%H:%M").unwrap();
+        assert_eq!(
+            time_window(time("19:30"), 30),
+            (time("19:00"), time("20:00"))
+        );
+
+        // Windows reaching past midnight stop at the end of the day searched
+        let (earliest, latest) = time_window(time("23:30"), 60);
+        assert_eq!(earliest, time("22:30"));
+        assert!(latest > time("23:59"));
+        let (earliest, latest) = time_window(time("00:30"), 60);
+        assert_eq!(earliest, time("00:00"));
+        assert_eq!(latest, time("01:30"));
+
+        // A flex of half a day or more covers the whole day instead of wrapping onto itself
+        let (earliest, latest) = time_window(time("12:00"), 720);
+        assert_eq!(earliest, time("00:00"));
+        assert!(latest > time("23:59"));
+        let (earliest, latest) = time_window(time("12:00"), u32::MAX);
+        assert_eq!(earliest, time("00:00"));
+        assert!(latest > time("23:59"));
+    }
+}
@@ -0,0 +1,874 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    extract::{Form, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Datelike, Days, Duration, Local, NaiveDate, NaiveTime, Weekday};
+use libresy::resy_data::{GeoFilter, RgsToken};
+use scenario::{PaymentMethodScenario, Scenario, SlotScenario, VenueScenario};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+pub mod scenario;
+
+/// Header the auth token is sent in, same as the real API.
+static RESY_AUTH_TOKEN_HEADER: &str = "X-Resy-Auth-Token";
+
+/// Prefix for book tokens handed out by the details endpoint.
+static BOOK_TOKEN_PREFIX: &str = "sim-book";
+
+/// A reservation booked through the simulator.
+#[derive(Debug, Clone)]
+struct Booking {
+    reservation_id: u64,
+    resy_token: String,
+    venue_id: u32,
+    day: NaiveDate,
+    time: NaiveTime,
+    party_size: u8,
+    table_type: String,
+    cancelled: bool,
+}
+
+/// Everything the simulator needs to keep track of between requests.
+struct SimState {
+    scenario: Scenario,
+    /// Token currently accepted and when it was issued, rotated on every login.
+    auth_token: Mutex<(Option<String>, Instant)>,
+    book_attempts: Mutex<HashMap<u32, u32>>,
+    /// Requests failed so far for each of the scenario's failures.
+    failures_served: Mutex<Vec<u32>>,
+    bookings: Mutex<Vec<Booking>>,
+    notifications: Mutex<Vec<Value>>,
+}
+
+impl SimState {
+    /// Current time on the server's clock.
+    fn now(&self) -> DateTime<Local> {
+        Local::now() + Duration::milliseconds(self.scenario.clock_offset_ms)
+    }
+
+    /// Adds the scenario's past reservations, the most recent first.
+    fn seed_past_bookings(&self) {
+        let Some(venue) = self.scenario.venues.first() else {
+            return;
+        };
+        let today = self.now().date_naive();
+        let mut bookings = self.bookings.lock().unwrap();
+        for days_ago in 1..=u64::from(self.scenario.past_reservations) {
+            let booking = Booking {
+                reservation_id: 700000000 + bookings.len() as u64,
+                resy_token: format!("sim-resy-{}", bookings.len()),
+                venue_id: venue.id,
+                day: today - Days::new(days_ago),
+                time: NaiveTime::from_hms_opt(19, 0, 0).unwrap(),
+                party_size: 2,
+                table_type: "Dining Room".to_owned(),
+                cancelled: false,
+            };
+            bookings.push(booking);
+        }
+    }
+
+    /// Whether the venue's slots have dropped yet today.
+    fn is_released(&self, venue: &VenueScenario) -> bool {
+        venue
+            .release_at
+            .is_none_or(|release_at| self.now().time() >= release_at)
+    }
+
+    /// Number of tables still open for a slot on a day.
+    fn remaining(&self, venue: &VenueScenario, slot: &SlotScenario, day: NaiveDate) -> u32 {
+        let booked = self
+            .bookings
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|b| {
+                !b.cancelled
+                    && b.venue_id == venue.id
+                    && b.day == day
+                    && b.time == slot.time
+                    && b.table_type == slot.table_type
+            })
+            .count() as u32;
+        slot.quantity.saturating_sub(booked)
+    }
+
+    /// Slots a party can still book at a venue on a day.
+    fn open_slots<'a>(
+        &self,
+        venue: &'a VenueScenario,
+        day: NaiveDate,
+        party_size: u8,
+    ) -> impl Iterator<Item = &'a SlotScenario> + use<'a, '_> {
+        let open = !venue.closed_on.contains(&day.weekday());
+        venue.slots.iter().filter(move |s| {
+            open && (s.min_size..=s.max_size).contains(&party_size)
+                && self.remaining(venue, s, day) > 0
+        })
+    }
+}
+
+/// First `weekday` after today on the machine's clock. Bookings made for it count as
+/// upcoming whenever they're made, which bookings for a fixed date stop doing once it
+/// has passed.
+pub fn next_weekday(weekday: Weekday) -> NaiveDate {
+    Local::now()
+        .date_naive()
+        .iter_days()
+        .skip(1)
+        .find(|day| day.weekday() == weekday)
+        .unwrap()
+}
+
+/// Builds the router serving the simulated API for a scenario.
+pub fn router(scenario: Scenario) -> Router {
+    let state = Arc::new(SimState {
+        auth_token: Mutex::new((scenario.auth.token.clone(), Instant::now())),
+        failures_served: Mutex::new(vec![0; scenario.failures.len()]),
+        scenario,
+        book_attempts: Mutex::new(HashMap::new()),
+        bookings: Mutex::new(Vec::new()),
+        notifications: Mutex::new(Vec::new()),
+    });
+    state.seed_past_bookings();
+    Router::new()
+        .route("/3/location/config", get(location_config))
+        .route("/3/venuesearch/search", post(venue_search))
+        .route("/4/find", get(find))
+        .route("/3/venue", get(venue))
+        .route("/4/venue/calendar", get(calendar))
+        .route("/3/details", post(details))
+        .route("/3/book", post(book))
+        .route(
+            "/3/notify",
+            get(list_notifications)
+                .post(create_notification)
+                .delete(delete_notification),
+        )
+        .route("/2/user", get(user))
+        .route("/3/user/reservations", get(user_reservations))
+        .route("/3/cancel", post(cancel))
+        .layer(middleware::from_fn_with_state(state.clone(), check_auth))
+        // Logging in is the only endpoint that doesn't need a valid token
+        .route("/3/auth/password", post(password_auth))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            inject_failures,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), stamp_date))
+        .with_state(state)
+}
+
+/// Binds to the address and serves the scenario in the background, returning the
+/// address actually bound (useful when binding to port 0 in tests.)
+pub async fn spawn(addr: SocketAddr, scenario: Scenario) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router(scenario)).await });
+    Ok(local_addr)
+}
+
+/// Error body in the same shape Resy sends.
+fn resy_error(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Json(json!({"status": status.as_u16(), "message": message, "code": null})),
+    )
+        .into_response()
+}
+
+/// Stamps responses with the server's time. Hyper's own Date header can be up to a second
+/// stale, which would throw off clients measuring the server's clock.
+async fn stamp_date(State(state): State<Arc<SimState>>, request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let date = state
+        .now()
+        .to_utc()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    if let Ok(date) = header::HeaderValue::from_str(&date) {
+        response.headers_mut().insert(header::DATE, date);
+    }
+    response
+}
+
+/// Fails requests to the endpoints the scenario makes fail, until each failure has been
+/// served as many times as asked.
+async fn inject_failures(
+    State(state): State<Arc<SimState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let failure = {
+        let mut served = state.failures_served.lock().unwrap();
+        state
+            .scenario
+            .failures
+            .iter()
+            .zip(served.iter_mut())
+            .find(|(f, served)| f.path == request.uri().path() && **served < f.count)
+            .map(|(f, served)| {
+                *served += 1;
+                f
+            })
+    };
+    let Some(failure) = failure else {
+        return next.run(request).await;
+    };
+    let status = StatusCode::from_u16(failure.status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+    let mut response = resy_error(status, status.canonical_reason().unwrap_or("Error"));
+    if let Some(retry_after) = failure.retry_after_secs {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, retry_after.into());
+    }
+    response
+}
+
+async fn check_auth(
+    State(state): State<Arc<SimState>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (token, issued) = state.auth_token.lock().unwrap().clone();
+    if let Some(expected) = &token {
+        let provided = headers
+            .get(RESY_AUTH_TOKEN_HEADER)
+            .and_then(|v| v.to_str().ok());
+        if provided != Some(expected.as_str()) {
+            return resy_error(StatusCode::UNAUTHORIZED, "Unauthorized");
+        }
+    }
+    if let Some(expires_after) = state.scenario.auth.expires_after_secs {
+        if issued.elapsed().as_secs_f64() > expires_after {
+            return resy_error(
+                StatusCode::from_u16(419).unwrap(),
+                "Authentication token expired",
+            );
+        }
+    }
+    next.run(request).await
+}
+
+async fn password_auth(
+    State(state): State<Arc<SimState>>,
+    Form(params): Form<HashMap<String, String>>,
+) -> Response {
+    let auth = &state.scenario.auth;
+    let matches = |expected: &Option<String>, key: &str| {
+        expected.is_none() || expected.as_ref() == params.get(key)
+    };
+    if !matches(&auth.email, "email") || !matches(&auth.password, "password") {
+        return resy_error(
+            StatusCode::from_u16(419).unwrap(),
+            "Incorrect email or password",
+        );
+    }
+    let mut auth_token = state.auth_token.lock().unwrap();
+    let token = format!(
+        "sim-token-{}",
+        Local::now().timestamp_nanos_opt().unwrap_or(0)
+    );
+    *auth_token = (Some(token.clone()), Instant::now());
+    Json(json!({
+        "id": 1,
+        "token": token,
+        "first_name": "Sim",
+        "last_name": "User",
+        "em_address": params.get("email"),
+        "payment_methods": payment_methods_json(&state.scenario.payment_methods),
+    }))
+    .into_response()
+}
+
+fn payment_methods_json(payment_methods: &[PaymentMethodScenario]) -> Vec<Value> {
+    payment_methods
+        .iter()
+        .map(|p| {
+            json!({
+                "id": p.id,
+                "is_default": p.is_default,
+                "provider_id": 1,
+                "provider_name": "braintree",
+                "display": p.last4,
+                "type": p.brand,
+                "exp_month": p.exp_month,
+                "exp_year": p.exp_year,
+            })
+        })
+        .collect()
+}
+
+async fn user(State(state): State<Arc<SimState>>) -> Json<Value> {
+    Json(json!({
+        "id": 1,
+        "first_name": "Sim",
+        "last_name": "User",
+        "payment_methods": payment_methods_json(&state.scenario.payment_methods),
+    }))
+}
+
+/// Sends the cities with an ETag and answers conditional requests for unchanged cities with
+/// a 304, like a CDN in front of the real endpoint would.
+async fn location_config(State(state): State<Arc<SimState>>, headers: HeaderMap) -> Response {
+    let cities = location_config_json(&state);
+    let mut hasher = DefaultHasher::new();
+    cities.to_string().hash(&mut hasher);
+    let etag = format!("\"{:x}\"", hasher.finish());
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|tag| tag.as_bytes() == etag.as_bytes())
+    {
+        return StatusCode::NOT_MODIFIED.into_response();
+    }
+    ([(header::ETAG, etag)], Json(cities)).into_response()
+}
+
+fn location_config_json(state: &SimState) -> Value {
+    json!(state
+        .scenario
+        .cities
+        .iter()
+        .enumerate()
+        .map(|(i, c)| json!({
+            "id": i + 1,
+            "name": c.name,
+            "code": c.code,
+            "url_slug": c.url_slug,
+            "country_code": c.country_code,
+            "latitude": c.latitude,
+            "longitude": c.longitude,
+            "time_zone": c.time_zone,
+        }))
+        .collect::<Vec<Value>>())
+}
+
+fn venue_json(state: &SimState, venue: &VenueScenario) -> Value {
+    let city = state
+        .scenario
+        .cities
+        .iter()
+        .find(|c| c.url_slug == venue.city);
+    json!({
+        "id": {"resy": venue.id},
+        "venue_group": {"id": venue.id, "name": venue.name, "venues": [venue.id]},
+        "name": venue.name,
+        "type": venue.cuisine,
+        "url_slug": venue.name.to_ascii_lowercase().replace(' ', "-"),
+        "price_range": venue.price_range,
+        "currency_symbol": "$",
+        "feature_recaptcha": false,
+        "rating": venue.rating,
+        "total_ratings": 100,
+        "location": {
+            "time_zone": city.map_or("EST5EDT", |c| c.time_zone.as_str()),
+            "neighborhood": venue.neighborhood,
+            "geo": {
+                "lat": city.map_or(0.0, |c| c.latitude),
+                "lon": city.map_or(0.0, |c| c.longitude),
+            },
+            "code": city.map_or("", |c| c.code.as_str()),
+            "name": city.map_or("", |c| c.name.as_str()),
+            "url_slug": venue.city,
+        },
+        "service_types": {"3": {}},
+        "waitlist": {"available": 0, "label": "Waitlist", "current": null},
+        "notify_options": [],
+        "currency": {"symbol": "$", "code": "USD"},
+        "default_template": venue.template_id.to_string(),
+    })
+}
+
+fn slot_json(venue: &VenueScenario, slot: &SlotScenario, day: NaiveDate, party_size: u8) -> Value {
+    let start = day.and_time(slot.time);
+    let end = start + Duration::minutes(slot.turn_minutes);
+    let token = RgsToken {
+        venue_id: venue.id.into(),
+        template_id: venue.template_id.into(),
+        service_type_id: slot.service_type_id.into(),
+        start_date: day,
+        end_date: day,
+        time: slot.time,
+        party_size,
+        table_type: slot.table_type.clone(),
+    }
+    .to_string();
+    let turn_seconds = slot.turn_minutes * 60;
+    let is_paid = slot.deposit_fee.is_some();
+    json!({
+        "availability": {"id": 3},
+        "config": {
+            "id": venue.id * 100 + slot.time.format("%H%M").to_string().parse::<u32>().unwrap_or(0),
+            "type": slot.table_type,
+            "token": token,
+        },
+        "date": {
+            "start": start.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "end": end.format("%Y-%m-%d %H:%M:%S").to_string(),
+        },
+        "market": {"date": {
+            "on": start.and_utc().timestamp() - 30 * 24 * 60 * 60,
+            "off": end.and_utc().timestamp(),
+        }},
+        "quantity": slot.quantity,
+        "score": {"total": 80.0},
+        "shift": {
+            "id": venue.template_id,
+            "service": {"type": {"id": slot.service_type_id}},
+            "day": day.to_string(),
+        },
+        "size": {"min": slot.min_size, "max": slot.max_size},
+        "template": {"id": venue.template_id},
+        "time": {"turn": {"actual": turn_seconds, "estimated": turn_seconds}},
+        "payment": {
+            "is_paid": is_paid,
+            "cancellation_fee": slot.cancellation_fee,
+            "deposit_fee": slot.deposit_fee,
+            "service_charge": null,
+            "venue_share": null,
+            "payment_structure": if is_paid { json!(1) } else { Value::Null },
+            "secs_cancel_cut_off": slot.cancellation_fee.map(|_| 86400),
+            "time_cancel_cut_off": null,
+            "secs_change_cut_off": null,
+            "time_change_cut_off": null,
+            "service_charge_options": [],
+        },
+    })
+}
+
+#[derive(Deserialize)]
+struct SearchRequest {
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    availability: bool,
+    geo: Option<GeoFilter>,
+    slot_filter: Option<SlotFilter>,
+    /// Starts at 1.
+    #[serde(default = "default_page")]
+    page: usize,
+    #[serde(default = "default_per_page")]
+    per_page: usize,
+}
+
+fn default_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    20
+}
+
+#[derive(Deserialize)]
+struct SlotFilter {
+    day: NaiveDate,
+    party_size: u8,
+}
+
+async fn venue_search(
+    State(state): State<Arc<SimState>>,
+    Json(request): Json<SearchRequest>,
+) -> Json<Value> {
+    let query = request.query.to_ascii_lowercase();
+    // Venues are placed at the center of their city
+    let location = |venue: &VenueScenario| -> (f64, f64) {
+        state
+            .scenario
+            .cities
+            .iter()
+            .find(|c| c.url_slug == venue.city)
+            .map_or((0.0, 0.0), |c| (c.latitude.into(), c.longitude.into()))
+    };
+    let in_area = |venue: &VenueScenario| {
+        request.geo.as_ref().is_none_or(|geo| {
+            let (latitude, longitude) = location(venue);
+            geo.contains(latitude, longitude)
+        })
+    };
+    let open_slots = |venue: &VenueScenario| -> Vec<Value> {
+        let Some(filter) = request
+            .slot_filter
+            .as_ref()
+            .filter(|_| request.availability)
+        else {
+            return Vec::new();
+        };
+        let released = state.is_released(venue);
+        state
+            .open_slots(venue, filter.day, filter.party_size)
+            .filter(|_| released)
+            .map(|s| slot_json(venue, s, filter.day, filter.party_size))
+            .collect()
+    };
+    let hits: Vec<Value> = state
+        .scenario
+        .venues
+        .iter()
+        .filter(|v| v.name.to_ascii_lowercase().contains(&query) && in_area(v))
+        .map(|v| (v, open_slots(v)))
+        .filter(|(_, slots)| !request.availability || !slots.is_empty())
+        .map(|(v, slots)| {
+            let (lat, lng) = location(v);
+            json!({
+                "objectID": v.id.to_string(),
+                "id": {"resy": v.id},
+                "name": v.name,
+                "cuisine": [v.cuisine],
+                "neighborhood": v.neighborhood,
+                "price_range_id": v.price_range,
+                "rating": {"average": v.rating, "count": 100},
+                "url_slug": v.name.to_ascii_lowercase().replace(' ', "-"),
+                "_geoloc": {"lat": lat, "lng": lng},
+                "availability": {"slots": slots},
+            })
+        })
+        .collect();
+    let per_page = request.per_page.max(1);
+    if state.scenario.search_ignores_paging {
+        let page: Vec<&Value> = hits.iter().take(per_page).collect();
+        return Json(json!({"search": {"nbHits": hits.len(), "hits": page}}));
+    }
+    let page: Vec<&Value> = hits
+        .iter()
+        .skip(request.page.saturating_sub(1) * per_page)
+        .take(per_page)
+        .collect();
+    Json(json!({"search": {
+        "nbHits": hits.len(),
+        "nbPages": hits.len().div_ceil(per_page),
+        "page": request.page,
+        "hitsPerPage": per_page,
+        "hits": page,
+    }}))
+}
+
+#[derive(Deserialize)]
+struct FindQuery {
+    venue_id: u32,
+    day: NaiveDate,
+    party_size: u8,
+}
+
+async fn find(State(state): State<Arc<SimState>>, Query(query): Query<FindQuery>) -> Json<Value> {
+    let Some(venue) = state.scenario.venue(query.venue_id) else {
+        return Json(json!({"results": {"venues": []}}));
+    };
+    if venue.find_hangs {
+        std::future::pending::<()>().await;
+    }
+    if venue.find_delay_ms > 0 {
+        tokio::time::sleep(std::time::Duration::from_millis(venue.find_delay_ms)).await;
+    }
+    let released = state.is_released(venue);
+    let slots: Vec<Value> = state
+        .open_slots(venue, query.day, query.party_size)
+        .filter(|_| released)
+        .map(|s| slot_json(venue, s, query.day, query.party_size))
+        .collect();
+    Json(json!({
+        "query": {"day": query.day.to_string(), "party_size": query.party_size, "time_filter": null},
+        "results": {"venues": [{"venue": venue_json(&state, venue), "slots": slots}]},
+    }))
+}
+
+#[derive(Deserialize)]
+struct VenueQuery {
+    id: u32,
+}
+
+async fn venue(State(state): State<Arc<SimState>>, Query(query): Query<VenueQuery>) -> Response {
+    match state.scenario.venue(query.id) {
+        Some(venue) => Json(venue_json(&state, venue)).into_response(),
+        None => resy_error(StatusCode::NOT_FOUND, "Venue not found"),
+    }
+}
+
+#[derive(Deserialize)]
+struct CalendarQuery {
+    venue_id: u32,
+    num_seats: u8,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+}
+
+async fn calendar(
+    State(state): State<Arc<SimState>>,
+    Query(query): Query<CalendarQuery>,
+) -> Response {
+    let Some(venue) = state.scenario.venue(query.venue_id) else {
+        return resy_error(StatusCode::NOT_FOUND, "Venue not found");
+    };
+    let scheduled: Vec<Value> = query
+        .start_date
+        .iter_days()
+        .take_while(|day| *day <= query.end_date)
+        .map(|day| {
+            let status = if venue.closed_on.contains(&day.weekday()) {
+                "closed"
+            } else if state.open_slots(venue, day, query.num_seats).next().is_some() {
+                "available"
+            } else {
+                "sold-out"
+            };
+            json!({
+                "date": day.to_string(),
+                "inventory": {"reservation": status, "event": "not available", "walk-in": "available"},
+            })
+        })
+        .collect();
+    Json(json!({
+        "last_calendar_day": query.end_date.to_string(),
+        "scheduled": scheduled,
+    }))
+    .into_response()
+}
+
+/// Looks up the slot a token points at, only returning it if it can still be booked.
+fn find_open_slot<'a>(
+    state: &'a SimState,
+    token: &RgsToken,
+) -> Option<(&'a VenueScenario, &'a SlotScenario)> {
+    let venue = state.scenario.venue(token.venue_id.try_into().ok()?)?;
+    let released = state.is_released(venue);
+    let slot = state
+        .open_slots(venue, token.start_date, token.party_size)
+        .find(|s| s.time == token.time && s.table_type == token.table_type)?;
+    released.then_some((venue, slot))
+}
+
+#[derive(Deserialize)]
+struct DetailsRequest {
+    config_id: String,
+}
+
+async fn details(
+    State(state): State<Arc<SimState>>,
+    Json(request): Json<DetailsRequest>,
+) -> Response {
+    let Ok(token) = request.config_id.parse::<RgsToken>() else {
+        return resy_error(StatusCode::BAD_REQUEST, "Invalid config_id");
+    };
+    let Some((_, slot)) = find_open_slot(&state, &token) else {
+        return resy_error(StatusCode::NOT_FOUND, "Reservation slot not found");
+    };
+    let party_size = f64::from(token.party_size);
+    Json(json!({
+        "book_token": {
+            "value": format!("{}|{}", BOOK_TOKEN_PREFIX, request.config_id),
+            "date_expires": (state.now() + Duration::minutes(5)).format("%Y-%m-%d %H:%M:%S").to_string(),
+        },
+        "user": {"payment_methods": payment_methods_json(&state.scenario.payment_methods)},
+        "payment": {
+            "amounts": {
+                "deposit_fee": slot.deposit_fee.map(|f| f * party_size),
+                "cancellation_fee": slot.cancellation_fee.map(|f| f * party_size),
+                "service_charge": null,
+            },
+        },
+        "cancellation": {
+            "fee": slot.cancellation_fee.map(|f| json!({"amount": f, "date_cut_off": null})),
+        },
+    }))
+    .into_response()
+}
+
+async fn book(
+    State(state): State<Arc<SimState>>,
+    Form(params): Form<HashMap<String, String>>,
+) -> Response {
+    let Some(token) = params
+        .get("book_token")
+        .and_then(|t| t.strip_prefix(BOOK_TOKEN_PREFIX))
+        .and_then(|t| t.strip_prefix('|'))
+        .and_then(|t| t.parse::<RgsToken>().ok())
+    else {
+        return resy_error(StatusCode::BAD_REQUEST, "Invalid book_token");
+    };
+    let payment_id = params
+        .get("struct_payment_method")
+        .and_then(|p| serde_json::from_str::<Value>(p).ok())
+        .and_then(|p| p["id"].as_u64());
+    let Some(payment_id) = payment_id else {
+        return resy_error(StatusCode::PAYMENT_REQUIRED, "Payment method required");
+    };
+    if !state
+        .scenario
+        .payment_methods
+        .iter()
+        .any(|p| u64::from(p.id) == payment_id)
+    {
+        return resy_error(StatusCode::BAD_REQUEST, "Invalid payment method");
+    }
+    let venue = u32::try_from(token.venue_id)
+        .ok()
+        .and_then(|id| state.scenario.venue(id));
+    let Some(venue) = venue else {
+        return resy_error(StatusCode::NOT_FOUND, "Venue not found");
+    };
+    let attempt = {
+        let mut attempts = state.book_attempts.lock().unwrap();
+        let count = attempts.entry(venue.id).or_insert(0);
+        *count += 1;
+        *count
+    };
+    let failure = (attempt <= venue.book_failures).then(|| {
+        StatusCode::from_u16(venue.book_failure_status).unwrap_or(StatusCode::PRECONDITION_FAILED)
+    });
+    if let Some(status) = failure.filter(|_| !venue.book_failures_commit) {
+        return resy_error(status, "Sorry, that reservation is no longer available");
+    }
+    if find_open_slot(&state, &token).is_none() {
+        return resy_error(
+            StatusCode::PRECONDITION_FAILED,
+            "Sorry, that reservation is no longer available",
+        );
+    }
+    let mut bookings = state.bookings.lock().unwrap();
+    let booking = Booking {
+        reservation_id: 700000000 + bookings.len() as u64,
+        resy_token: format!("sim-resy-{}", bookings.len()),
+        venue_id: venue.id,
+        day: token.start_date,
+        time: token.time,
+        party_size: token.party_size,
+        table_type: token.table_type,
+        cancelled: false,
+    };
+    let response = json!({
+        "resy_token": booking.resy_token,
+        "reservation_id": booking.reservation_id,
+    });
+    bookings.push(booking);
+    if let Some(status) = failure {
+        return resy_error(status, "Sorry, that reservation is no longer available");
+    }
+    Json(response).into_response()
+}
+
+#[derive(Deserialize)]
+struct UserReservationsQuery {
+    #[serde(rename = "type")]
+    reservation_type: String,
+    limit: Option<usize>,
+    /// Position of the first reservation to return, starting at 1.
+    offset: Option<usize>,
+}
+
+async fn user_reservations(
+    State(state): State<Arc<SimState>>,
+    Query(query): Query<UserReservationsQuery>,
+) -> Json<Value> {
+    let today = state.now().date_naive();
+    let upcoming = query.reservation_type == "upcoming";
+    let bookings = state.bookings.lock().unwrap();
+    let reservations: Vec<Value> = bookings
+        .iter()
+        .filter(|b| upcoming == (!b.cancelled && b.day >= today))
+        .skip(query.offset.unwrap_or(1).saturating_sub(1))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .map(|b| {
+            json!({
+                "reservation_id": b.reservation_id,
+                "resy_token": b.resy_token,
+                "day": b.day.to_string(),
+                "time_slot": b.time.format("%H:%M:%S").to_string(),
+                "num_seats": b.party_size,
+                "venue": {"id": b.venue_id},
+                "config": {"type": b.table_type},
+            })
+        })
+        .collect();
+    let venues: HashMap<String, Value> = state
+        .scenario
+        .venues
+        .iter()
+        .map(|v| (v.id.to_string(), json!({"name": v.name})))
+        .collect();
+    Json(json!({"reservations": reservations, "venues": venues}))
+}
+
+async fn cancel(
+    State(state): State<Arc<SimState>>,
+    Form(params): Form<HashMap<String, String>>,
+) -> Response {
+    let mut bookings = state.bookings.lock().unwrap();
+    match bookings
+        .iter_mut()
+        .find(|b| Some(&b.resy_token) == params.get("resy_token") && !b.cancelled)
+    {
+        Some(booking) => {
+            booking.cancelled = true;
+            Json(json!({"payment": {"transaction": {"refund": 0}}})).into_response()
+        }
+        None => resy_error(StatusCode::NOT_FOUND, "Reservation not found"),
+    }
+}
+
+async fn list_notifications(State(state): State<Arc<SimState>>) -> Json<Value> {
+    Json(json!({"notify": *state.notifications.lock().unwrap()}))
+}
+
+/// Notifications are unique by venue, day, party size and service type, same as Resy.
+fn same_notification(a: &Value, b: &Value) -> bool {
+    ["venue_id", "day", "party_size", "service_type_id"]
+        .iter()
+        .all(|key| a["specs"][key] == b["specs"][key])
+}
+
+async fn create_notification(
+    State(state): State<Arc<SimState>>,
+    Form(params): Form<HashMap<String, String>>,
+) -> Response {
+    let Some(notification) = params
+        .get("struct_data")
+        .and_then(|d| serde_json::from_str::<Value>(d).ok())
+    else {
+        return resy_error(StatusCode::BAD_REQUEST, "Invalid struct_data");
+    };
+    let mut notifications = state.notifications.lock().unwrap();
+    notifications.retain(|n| !same_notification(n, &notification));
+    notifications.push(notification);
+    Json(json!({})).into_response()
+}
+
+#[derive(Deserialize)]
+struct DeleteNotificationQuery {
+    venue_id: u32,
+    day: String,
+    num_seats: u8,
+    service_type_id: u8,
+}
+
+async fn delete_notification(
+    State(state): State<Arc<SimState>>,
+    Query(query): Query<DeleteNotificationQuery>,
+) -> Response {
+    let target = json!({"specs": {
+        "venue_id": query.venue_id,
+        "day": query.day,
+        "party_size": query.num_seats,
+        "service_type_id": query.service_type_id,
+    }});
+    let mut notifications = state.notifications.lock().unwrap();
+    let before = notifications.len();
+    notifications.retain(|n| !same_notification(n, &target));
+    if notifications.len() == before {
+        return resy_error(StatusCode::NOT_FOUND, "Notification not found");
+    }
+    Json(json!({})).into_response()
+}
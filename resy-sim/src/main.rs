@@ -0,0 +1,71 @@
+use std::{net::SocketAddr, path::PathBuf, time::Instant};
+
+use axum::{
+    extract::Request,
+    middleware::{self, Next},
+    response::Response,
+};
+use clap::Parser;
+use clap_verbosity_flag::LevelFilter;
+use resy_sim::scenario::Scenario;
+use tokio::net::TcpListener;
+
+#[derive(Parser)]
+#[command(author, version, about)]
+struct Cli {
+    #[command(flatten)]
+    verbose: clap_verbosity_flag::Verbosity,
+    /// Address to serve the simulated API on. Point the other tools at it with
+    /// --base-url http://<address>
+    #[arg(long, env, default_value = "127.0.0.1:8080")]
+    bind: SocketAddr,
+    /// JSON file describing the scenario to simulate. A single venue with open slots is
+    /// simulated if no scenario is provided.
+    #[arg(long, env)]
+    scenario: Option<PathBuf>,
+}
+
+/// Prints every request along with the status it was answered with and how long it took.
+async fn log_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    println!(
+        "{} {} -> {} in {}ms",
+        method,
+        uri,
+        response.status().as_u16(),
+        started.elapsed().as_millis()
+    );
+    response
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let scenario = match &cli.scenario {
+        Some(path) => Scenario::from_file(path)?,
+        None => Scenario::default(),
+    };
+    for venue in scenario.venues.iter() {
+        println!(
+            "Simulating {} (Resy ID = {}) with {} slots",
+            venue.name,
+            venue.id,
+            venue.slots.len()
+        );
+    }
+
+    let mut router = resy_sim::router(scenario);
+    // Requests are only logged when asked for with -v
+    if cli.verbose.log_level_filter() > LevelFilter::Error {
+        router = router.layer(middleware::from_fn(log_request));
+    }
+
+    let listener = TcpListener::bind(cli.bind).await?;
+    println!("Serving simulated Resy API on http://{}", cli.bind);
+    axum::serve(listener, router).await?;
+    Ok(())
+}
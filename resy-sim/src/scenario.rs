@@ -0,0 +1,377 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use chrono::{NaiveTime, Weekday};
+use serde::Deserialize;
+
+/// Describes how the simulated Resy API should behave. Scenarios are loaded from JSON
+/// files, every field has a default so a scenario only needs to describe what it cares
+/// about.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Scenario {
+    #[serde(default)]
+    pub auth: AuthScenario,
+    #[serde(default = "default_cities")]
+    pub cities: Vec<CityScenario>,
+    #[serde(default = "default_venues")]
+    pub venues: Vec<VenueScenario>,
+    /// Cards on the simulated account.
+    #[serde(default = "default_payment_methods")]
+    pub payment_methods: Vec<PaymentMethodScenario>,
+    /// Milliseconds the server's clock runs ahead of the machine's, negative if it's
+    /// behind. Shifts the Date header and when venues release their slots.
+    #[serde(default)]
+    pub clock_offset_ms: i64,
+    /// Number of reservations the account already went to, one a day at the first venue
+    /// going back from yesterday.
+    #[serde(default)]
+    pub past_reservations: u32,
+    /// Venue search always answers with the first page and leaves out the page count, like
+    /// a server that doesn't support paging.
+    #[serde(default)]
+    pub search_ignores_paging: bool,
+    /// Endpoints that fail their first requests, like Resy does when it's overloaded.
+    #[serde(default)]
+    pub failures: Vec<FailureScenario>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            auth: AuthScenario::default(),
+            cities: default_cities(),
+            venues: default_venues(),
+            payment_methods: default_payment_methods(),
+            clock_offset_ms: 0,
+            past_reservations: 0,
+            search_ignores_paging: false,
+            failures: Vec::new(),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn from_file(path: &Path) -> anyhow::Result<Scenario> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn venue(&self, id: u32) -> Option<&VenueScenario> {
+        self.venues.iter().find(|v| v.id == id)
+    }
+}
+
+/// Controls what auth token is accepted and for how long.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuthScenario {
+    /// Token that must be sent in the X-Resy-Auth-Token header. Any token is accepted if
+    /// this isn't set.
+    pub token: Option<String>,
+    /// Seconds after the server starts (or after the last login) that the token stops
+    /// being accepted, after which every request gets a 419 like Resy sends for expired
+    /// tokens.
+    pub expires_after_secs: Option<f64>,
+    /// Email accepted by the password login endpoint. Any email is accepted if not set.
+    pub email: Option<String>,
+    /// Password accepted by the password login endpoint. Any password is accepted if not set.
+    pub password: Option<String>,
+}
+
+/// Makes the first requests to an endpoint fail before it starts answering normally.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FailureScenario {
+    /// Path of the endpoint, e.g. `/4/find`.
+    pub path: String,
+    /// Number of requests that fail.
+    pub count: u32,
+    /// Status returned for the failed requests, e.g. 503 or 429.
+    #[serde(default = "default_failure_status")]
+    pub status: u16,
+    /// Seconds sent in a Retry-After header with the failed requests, none is sent if
+    /// this isn't set.
+    pub retry_after_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PaymentMethodScenario {
+    pub id: u32,
+    #[serde(default = "default_card_brand")]
+    pub brand: String,
+    pub last4: String,
+    #[serde(default = "default_exp_month")]
+    pub exp_month: u8,
+    #[serde(default = "default_exp_year")]
+    pub exp_year: u16,
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CityScenario {
+    pub name: String,
+    pub code: String,
+    pub url_slug: String,
+    pub country_code: String,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub time_zone: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct VenueScenario {
+    pub id: u32,
+    pub name: String,
+    #[serde(default = "default_cuisine")]
+    pub cuisine: String,
+    #[serde(default)]
+    pub neighborhood: String,
+    /// url_slug of the city (from `cities`) the venue is in.
+    pub city: String,
+    #[serde(default = "default_price_range")]
+    pub price_range: u8,
+    #[serde(default = "default_rating")]
+    pub rating: f32,
+    /// Template the generated slot tokens reference.
+    #[serde(default = "default_template_id")]
+    pub template_id: u32,
+    /// Time of day (sim server's local time) slots become visible through /4/find. Before
+    /// this the venue is returned without any slots, like Resy does before a drop.
+    pub release_at: Option<NaiveTime>,
+    /// Days of the week the venue doesn't take reservations, e.g. `["Mon"]`.
+    #[serde(default)]
+    pub closed_on: Vec<Weekday>,
+    /// Milliseconds /4/find takes to answer, like Resy does when it's swamped at a drop.
+    #[serde(default)]
+    pub find_delay_ms: u64,
+    /// /4/find never answers, like a request lost in the traffic of a drop.
+    #[serde(default)]
+    pub find_hangs: bool,
+    /// Number of booking attempts that fail before bookings start succeeding.
+    #[serde(default)]
+    pub book_failures: u32,
+    /// Status returned for the failed booking attempts.
+    #[serde(default = "default_book_failure_status")]
+    pub book_failure_status: u16,
+    /// Whether the failed booking attempts still book the slot, like a request that errors
+    /// out after Resy took the table.
+    #[serde(default)]
+    pub book_failures_commit: bool,
+    pub slots: Vec<SlotScenario>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SlotScenario {
+    pub time: NaiveTime,
+    #[serde(default = "default_table_type")]
+    pub table_type: String,
+    /// How long the table is held for, in minutes.
+    #[serde(default = "default_turn_minutes")]
+    pub turn_minutes: i64,
+    /// Resy service type, 3 seems to be dinner.
+    #[serde(default = "default_service_type_id")]
+    pub service_type_id: u8,
+    #[serde(default = "default_min_size")]
+    pub min_size: u8,
+    #[serde(default = "default_max_size")]
+    pub max_size: u8,
+    /// How many tables are available for the slot, each booking takes one.
+    #[serde(default = "default_quantity")]
+    pub quantity: u32,
+    /// Deposit per guest, makes the slot a paid slot.
+    pub deposit_fee: Option<f64>,
+    /// Fee per guest charged when cancelling too late.
+    pub cancellation_fee: Option<f64>,
+}
+
+fn default_failure_status() -> u16 {
+    503
+}
+
+fn default_card_brand() -> String {
+    "visa".to_owned()
+}
+
+fn default_exp_month() -> u8 {
+    12
+}
+
+fn default_exp_year() -> u16 {
+    2030
+}
+
+fn default_payment_methods() -> Vec<PaymentMethodScenario> {
+    vec![PaymentMethodScenario {
+        id: 1234567,
+        brand: default_card_brand(),
+        last4: "4242".to_owned(),
+        exp_month: default_exp_month(),
+        exp_year: default_exp_year(),
+        is_default: true,
+    }]
+}
+
+fn default_cuisine() -> String {
+    "Italian".to_owned()
+}
+
+fn default_price_range() -> u8 {
+    2
+}
+
+fn default_rating() -> f32 {
+    4.5
+}
+
+fn default_template_id() -> u32 {
+    1844312
+}
+
+fn default_book_failure_status() -> u16 {
+    412
+}
+
+fn default_table_type() -> String {
+    "Dining Room".to_owned()
+}
+
+fn default_turn_minutes() -> i64 {
+    90
+}
+
+fn default_service_type_id() -> u8 {
+    3
+}
+
+fn default_min_size() -> u8 {
+    1
+}
+
+fn default_max_size() -> u8 {
+    4
+}
+
+fn default_quantity() -> u32 {
+    1
+}
+
+fn default_cities() -> Vec<CityScenario> {
+    vec![
+        CityScenario {
+            name: "New York".to_owned(),
+            code: "ny".to_owned(),
+            url_slug: "new-york-ny".to_owned(),
+            country_code: "US".to_owned(),
+            latitude: 40.71294,
+            longitude: -74.00639,
+            time_zone: "EST5EDT".to_owned(),
+        },
+        CityScenario {
+            name: "Philadelphia".to_owned(),
+            code: "pha".to_owned(),
+            url_slug: "philadelphia-pa".to_owned(),
+            country_code: "US".to_owned(),
+            latitude: 39.95258,
+            longitude: -75.16522,
+            time_zone: "EST5EDT".to_owned(),
+        },
+    ]
+}
+
+fn default_venues() -> Vec<VenueScenario> {
+    let slots = [
+        "12:00", "12:15", "12:30", "12:45", "13:00", "19:00", "19:30",
+    ]
+    .iter()
+    .map(|t| SlotScenario {
+        time: NaiveTime::parse_from_str(t, "%H:%M").unwrap(),
+        table_type: "Inside".to_owned(),
+        turn_minutes: default_turn_minutes(),
+        service_type_id: default_service_type_id(),
+        min_size: default_min_size(),
+        max_size: default_max_size(),
+        quantity: 2,
+        deposit_fee: None,
+        cancellation_fee: None,
+    })
+    .collect();
+    vec![VenueScenario {
+        id: 65615,
+        name: "Pizzeria Stella".to_owned(),
+        cuisine: default_cuisine(),
+        neighborhood: "Headhouse Square".to_owned(),
+        city: "philadelphia-pa".to_owned(),
+        price_range: default_price_range(),
+        rating: 4.78,
+        template_id: default_template_id(),
+        release_at: None,
+        closed_on: vec![Weekday::Mon],
+        find_delay_ms: 0,
+        find_hangs: false,
+        book_failures: 0,
+        book_failure_status: default_book_failure_status(),
+        book_failures_commit: false,
+        slots,
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    /// Writes `contents` to a scenario file only this test uses.
+    fn scenario_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("resy-sim-{}-{}.json", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_scenario() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios/drop.json");
+        let scenario = Scenario::from_file(&path).unwrap();
+        assert_eq!(scenario.auth.token.as_deref(), Some("sim-token"));
+        let venue = scenario.venue(65615).unwrap();
+        assert_eq!(venue.book_failures, 2);
+        assert_eq!(venue.book_failure_status, 412);
+        assert!(!venue.book_failures_commit);
+        assert_eq!(
+            venue.release_at,
+            NaiveTime::from_hms_milli_opt(9, 0, 0, 250)
+        );
+        assert_eq!(venue.slots.len(), 4);
+        assert_eq!(venue.slots[0].quantity, 1);
+        assert_eq!(venue.slots[3].deposit_fee, Some(25.0));
+        // Left out of the file so the defaults are used
+        assert_eq!(scenario.cities.len(), default_cities().len());
+        assert_eq!(scenario.payment_methods[0].last4, "4242");
+        assert!(scenario.failures.is_empty());
+
+        let path = scenario_file("empty", "{}");
+        let empty = Scenario::from_file(&path).unwrap();
+        assert_eq!(empty.venues.len(), 1);
+        assert_eq!(empty.venues[0].book_failures, 0);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_malformed_scenario() {
+        assert!(Scenario::from_file(Path::new("missing-scenario.json")).is_err());
+        for (name, contents) in [
+            ("truncated", r#"{"venues": ["#),
+            (
+                "wrong-type",
+                r#"{"venues": [{"id": "65615", "name": "Stella", "city": "philadelphia-pa", "slots": []}]}"#,
+            ),
+            (
+                "bad-time",
+                r#"{"venues": [{"id": 1, "name": "Stella", "city": "philadelphia-pa", "slots": [{"time": "7pm"}]}]}"#,
+            ),
+            ("bad-failure", r#"{"failures": [{"path": "/4/find"}]}"#),
+        ] {
+            let path = scenario_file(name, contents);
+            assert!(Scenario::from_file(&path).is_err(), "{}", name);
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
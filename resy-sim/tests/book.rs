@@ -0,0 +1,84 @@
+use chrono::Weekday;
+use libresy::{
+    resy_data::{BookingSpecs, ReservationDetails, ReservationSlot},
+    ResyClient, ResyClientBuilder, ResyError,
+};
+use resy_sim::scenario::Scenario;
+
+/// Client for a fresh sim serving the scenario, along with the first open slot on a
+/// Tuesday and its details.
+async fn spawn_slot(scenario: Scenario) -> (ResyClient, ReservationSlot, ReservationDetails) {
+    let addr = resy_sim::spawn("127.0.0.1:0".parse().unwrap(), scenario)
+        .await
+        .unwrap();
+    let resy_client = ResyClientBuilder::new("key".to_owned(), "token".to_owned())
+        .base_url(&format!("http://{}", addr))
+        .no_cache()
+        .build()
+        .unwrap();
+    let date = resy_sim::next_weekday(Weekday::Tue);
+    let slot = resy_client
+        .get_reservations("65615", &date, 2)
+        .await
+        .unwrap()
+        .remove(0);
+    let details = resy_client
+        .get_reservation_details(&slot, &date, 2)
+        .await
+        .unwrap();
+    (resy_client, slot, details)
+}
+
+#[tokio::test]
+async fn test_book_failures() {
+    let mut scenario = Scenario::default();
+    scenario.venues[0].book_failures = 2;
+    let (resy_client, slot, details) = spawn_slot(scenario).await;
+    let specs = BookingSpecs::for_slot(&slot, 2).unwrap();
+    let payment = details.get_payment_id().unwrap();
+
+    for _ in 0..2 {
+        assert!(matches!(
+            resy_client
+                .book_restaurant(&details, &specs, &payment)
+                .await,
+            Err(ResyError::BookingConflict(_))
+        ));
+    }
+    assert!(resy_client
+        .get_upcoming_reservations()
+        .await
+        .unwrap()
+        .is_empty());
+    let confirmation = resy_client
+        .book_restaurant(&details, &specs, &payment)
+        .await
+        .unwrap();
+    assert_eq!(confirmation.time, slot.date.start.time());
+    assert_eq!(
+        resy_client.get_upcoming_reservations().await.unwrap().len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_book_failures_commit() {
+    let mut scenario = Scenario::default();
+    scenario.venues[0].book_failures = 1;
+    scenario.venues[0].book_failure_status = 500;
+    scenario.venues[0].book_failures_commit = true;
+    let (resy_client, slot, details) = spawn_slot(scenario).await;
+    let specs = BookingSpecs::for_slot(&slot, 2).unwrap();
+    let payment = details.get_payment_id().unwrap();
+
+    // The request errors out but the table was taken anyway
+    assert!(matches!(
+        resy_client
+            .book_restaurant(&details, &specs, &payment)
+            .await,
+        Err(ResyError::Api { status: 500, .. })
+    ));
+    let booked = resy_client.get_upcoming_reservations().await.unwrap();
+    assert_eq!(booked.len(), 1);
+    assert_eq!(booked[0].resy_token, "sim-resy-0");
+}